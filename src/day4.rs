@@ -35,6 +35,56 @@ impl Pair {
         Self { left, right }
     }
 }
+// Any number of elves sharing a line, eg 2-3,4-5,1-9. A Pair is the two elf case.
+struct Group {
+    ranges: Vec<Range>,
+}
+impl Group {
+    pub fn new(line: &str) -> Self {
+        let ranges = line.split(',').map(Range::new).collect();
+        Self { ranges }
+    }
+}
+impl From<&Pair> for Group {
+    fn from(p: &Pair) -> Self {
+        Self {
+            ranges: vec![p.left, p.right],
+        }
+    }
+}
+type GroupContainment = fn(&Group) -> bool;
+// Some elf's range fully contains a different elf's range.
+fn any_contains(g: &Group) -> bool {
+    g.ranges.iter().enumerate().any(|(i, outer)| {
+        g.ranges
+            .iter()
+            .enumerate()
+            .any(|(j, inner)| i != j && outer.contains(*inner))
+    })
+}
+// There is a section every elf in the group cleans.
+fn common_section(g: &Group) -> bool {
+    let lower = g.ranges.iter().map(|r| r.lower).max();
+    let upper = g.ranges.iter().map(|r| r.upper).min();
+    lower <= upper
+}
+// Every elf is linked to every other by a chain of overlapping ranges.
+fn chained(g: &Group) -> bool {
+    let mut sorted = g.ranges.clone();
+    sorted.sort_by_key(|r| r.lower);
+    let mut reach = sorted.first().map_or(0, |r| r.upper);
+    sorted.iter().skip(1).all(|r| {
+        let linked = r.lower <= reach;
+        reach = reach.max(r.upper);
+        linked
+    })
+}
+
+fn eval_groups(lines: &[&str], f: GroupContainment) {
+    let total = lines.iter().filter(|line| f(&Group::new(line))).count();
+    println!("{}", total)
+}
+
 type Containment = fn(&Pair) -> bool;
 fn full(p: &Pair) -> bool {
     p.left.contains(p.right) || p.right.contains(p.left)
@@ -55,6 +105,243 @@ fn eval(lines: &Vec<&str>, f: Containment) {
     println!("{}", total)
 }
 
+// Every elf's range, sorted on the lower bound. Alongside each entry we keep
+// the largest upper bound found in the implicit balanced tree rooted at it
+// (the middle of each sub slice is the root of that slice) so whole subtrees
+// that finish before the query starts can be skipped.
+struct RangeIndex {
+    entries: Vec<(Range, usize)>,
+    max_upper: Vec<usize>,
+}
+
+impl RangeIndex {
+    pub fn new(ranges: &[Range]) -> Self {
+        let mut entries = ranges
+            .iter()
+            .enumerate()
+            .map(|(elf, r)| (*r, elf))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(r, _)| r.lower);
+        let mut max_upper = vec![0; entries.len()];
+        Self::build(&entries, &mut max_upper, 0, entries.len());
+        Self { entries, max_upper }
+    }
+    fn build(entries: &[(Range, usize)], max_upper: &mut [usize], lo: usize, hi: usize) -> usize {
+        if lo >= hi {
+            return 0;
+        }
+        let mid = (lo + hi) / 2;
+        let left = Self::build(entries, max_upper, lo, mid);
+        let right = Self::build(entries, max_upper, mid + 1, hi);
+        max_upper[mid] = entries[mid].0.upper.max(left).max(right);
+        max_upper[mid]
+    }
+    fn collect(&self, lo: usize, hi: usize, end: usize, from: usize, found: &mut Vec<usize>) {
+        //nothing in here starts early enough or finishes late enough?
+        if lo >= hi || lo >= end {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        if self.max_upper[mid] < from {
+            return;
+        }
+        self.collect(lo, mid, end, from, found);
+        if mid < end && self.entries[mid].0.upper >= from {
+            found.push(self.entries[mid].1);
+        }
+        self.collect(mid + 1, hi, end, from, found);
+    }
+    // Elves whose range shares at least one section with `r`.
+    pub fn overlapping(&self, r: Range) -> Vec<usize> {
+        let end = self.entries.partition_point(|(e, _)| e.lower <= r.upper);
+        let mut found = vec![];
+        self.collect(0, self.entries.len(), end, r.lower, &mut found);
+        found.sort();
+        found
+    }
+    // Elves whose range covers `section`.
+    pub fn stabbing(&self, section: usize) -> Vec<usize> {
+        self.overlapping(Range {
+            lower: section,
+            upper: section,
+        })
+    }
+}
+
+// Every range of each line, with the line each elf came from alongside.
+fn all_ranges(lines: &[&str]) -> (Vec<Range>, Vec<usize>) {
+    lines
+        .iter()
+        .enumerate()
+        .flat_map(|(i, line)| Group::new(line).ranges.into_iter().map(move |r| (r, i)))
+        .unzip()
+}
+
+// Lines with an elf whose range overlaps an elf from some other line.
+fn pairs_overlapping_others(ranges: &[Range], line_of: &[usize]) -> Vec<usize> {
+    let index = RangeIndex::new(ranges);
+    let mut lines = (0..ranges.len())
+        .filter(|elf| {
+            index
+                .overlapping(ranges[*elf])
+                .iter()
+                .any(|other| line_of[*other] != line_of[*elf])
+        })
+        .map(|elf| line_of[elf])
+        .collect::<Vec<_>>();
+    lines.dedup();
+    lines
+}
+
+// Union of all the sections cleaned, the holes between the first and last
+// section, and how many elves clean each section as runs of the same count, so
+// memory follows the number of ranges rather than how far apart they are.
+struct Coverage {
+    covered: Vec<Range>,
+    gaps: Vec<Range>,
+    //in order, only where at least one elf cleans
+    counts: Vec<(Range, usize)>,
+}
+impl Coverage {
+    pub fn new(ranges: &[Range]) -> Self {
+        let mut sorted = ranges.to_vec();
+        sorted.sort_by_key(|r| r.lower);
+
+        let mut covered: Vec<Range> = vec![];
+        sorted.iter().for_each(|r| match covered.last_mut() {
+            //touching counts too, 2-4 and 5-6 leave no section uncleaned
+            Some(last) if r.lower <= last.upper.saturating_add(1) => {
+                last.upper = last.upper.max(r.upper)
+            }
+            _ => covered.push(*r),
+        });
+        let gaps = covered
+            .windows(2)
+            .map(|w| Range {
+                lower: w[0].upper + 1,
+                upper: w[1].lower - 1,
+            })
+            .collect::<Vec<_>>();
+
+        //+1 at the start of each range and -1 just after the end, swept in order
+        let mut events = ranges
+            .iter()
+            .flat_map(|r| [(Some(r.lower), 1), (r.upper.checked_add(1), -1)])
+            //an end past usize::MAX never comes
+            .filter_map(|(at, delta)| Some((at?, delta)))
+            .collect::<Vec<(usize, isize)>>();
+        events.sort();
+        let mut counts: Vec<(Range, usize)> = vec![];
+        let mut running = 0;
+        for (i, (at, delta)) in events.iter().enumerate() {
+            running += delta;
+            if running == 0 || events.get(i + 1).is_some_and(|next| next.0 == *at) {
+                continue;
+            }
+            let upper = events.get(i + 1).map_or(usize::MAX, |next| next.0 - 1);
+            let count = running as usize;
+            match counts.last_mut() {
+                Some((last, c)) if *c == count && last.upper + 1 == *at => last.upper = upper,
+                _ => counts.push((Range { lower: *at, upper }, count)),
+            }
+        }
+
+        Self {
+            covered,
+            gaps,
+            counts,
+        }
+    }
+    //only the tests look up single sections
+    #[cfg(test)]
+    fn count(&self, section: usize) -> usize {
+        let ix = self.counts.partition_point(|(r, _)| r.upper < section);
+        self.counts
+            .get(ix)
+            .filter(|(r, _)| r.lower <= section)
+            .map_or(0, |(_, count)| *count)
+    }
+    // Runs of sections cleaned by more than `limit` elves.
+    pub fn crowded(&self, limit: usize) -> Vec<Range> {
+        let mut runs: Vec<Range> = vec![];
+        self.counts
+            .iter()
+            .filter(|(_, count)| *count > limit)
+            .for_each(|(r, _)| match runs.last_mut() {
+                Some(last) if last.upper + 1 == r.lower => last.upper = r.upper,
+                _ => runs.push(*r),
+            });
+        runs
+    }
+    pub fn report(&self, limit: usize) {
+        let show = |rs: &Vec<Range>| {
+            rs.iter()
+                .map(|r| format!("{}-{}", r.lower, r.upper))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        println!("Covered: {}", show(&self.covered));
+        println!("Gaps: {}", show(&self.gaps));
+        let widest = self
+            .counts
+            .iter()
+            .map(|(_, c)| *c)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        self.counts.iter().for_each(|(r, count)| {
+            //scale bars so the busiest section is 50 wide
+            let bar = "#".repeat(count * 50 / widest);
+            let flag = if *count > limit { " !" } else { "" };
+            let run = format!("{}-{}", r.lower, r.upper);
+            println!("{run:>11} {count:>5} {bar}{flag}");
+        });
+        let crowded = self.crowded(limit);
+        println!(
+            "{} sections covered by more than {limit} elves",
+            crowded.iter().map(|r| r.upper - r.lower + 1).sum::<usize>()
+        );
+    }
+}
+
+pub(crate) fn cli(args: &[String]) {
+    let lines = load_str!("../data/day4.txt")
+        .split('\n')
+        .collect::<Vec<_>>();
+    let (ranges, line_of) = all_ranges(&lines);
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["stab", section] => {
+            let section = section.parse::<usize>().expect("section must be a number");
+            let elves = RangeIndex::new(&ranges).stabbing(section);
+            println!("{} elves cover section {section}", elves.len());
+            elves.iter().for_each(|elf| {
+                let r = ranges[*elf];
+                println!("elf {elf} (line {}) {}-{}", line_of[*elf], r.lower, r.upper)
+            });
+        }
+        ["overlaps"] => {
+            let pairs = pairs_overlapping_others(&ranges, &line_of);
+            println!("{} pairs overlap another pair", pairs.len());
+            pairs.iter().for_each(|pair| println!("{}", lines[*pair]));
+        }
+        ["coverage", limit] => {
+            let limit = limit.parse::<usize>().expect("limit must be a number");
+            Coverage::new(&ranges).report(limit);
+        }
+        ["groups", rule] => {
+            let f: GroupContainment = match rule {
+                "contains" => any_contains,
+                "common" => common_section,
+                "chain" => chained,
+                _ => panic!("unknown rule {rule}, expected contains, common or chain"),
+            };
+            eval_groups(&lines, f);
+        }
+        _ => run(),
+    }
+}
+
 pub(crate) fn run() {
     let lines = load_str!("../data/day4.txt")
         .split('\n')
@@ -70,7 +357,10 @@ pub(crate) fn run() {
 }
 #[cfg(test)]
 mod tests {
-    use crate::day4::{full, partial, Pair, Range};
+    use crate::day4::{
+        all_ranges, any_contains, chained, common_section, full, pairs_overlapping_others, partial,
+        Coverage, Group, Pair, Range, RangeIndex,
+    };
 
     #[test]
     fn create() {
@@ -172,4 +462,152 @@ mod tests {
 
         assert_eq!(4, total)
     }
+    #[test]
+    fn index_stabbing() {
+        let data = r#"2-4,6-8
+2-3,4-5
+5-7,7-9
+2-8,3-7
+6-6,4-6
+2-6,4-8"#;
+        let lines = data.split('\n').collect::<Vec<_>>();
+        let (ranges, _) = all_ranges(&lines);
+        let index = RangeIndex::new(&ranges);
+        assert_eq!(vec![0, 2, 6, 7, 10], index.stabbing(3));
+        assert_eq!(vec![1, 4, 6, 7, 8, 9, 10, 11], index.stabbing(6));
+        assert_eq!(Vec::<usize>::new(), index.stabbing(10));
+        //brute force agrees everywhere
+        for section in 0..11 {
+            let expected = (0..ranges.len())
+                .filter(|elf| ranges[*elf].lower <= section && ranges[*elf].upper >= section)
+                .collect::<Vec<_>>();
+            assert_eq!(expected, index.stabbing(section));
+        }
+    }
+    #[test]
+    fn index_overlapping() {
+        let lines = vec!["1-2,10-12", "3-4,20-30", "11-11,40-40"];
+        let (ranges, line_of) = all_ranges(&lines);
+        let index = RangeIndex::new(&ranges);
+        assert_eq!(vec![1, 4], index.overlapping(Range::new("9-11")));
+        assert_eq!(vec![0, 2], index.overlapping(Range::new("2-3")));
+        assert_eq!(vec![0, 2], pairs_overlapping_others(&ranges, &line_of));
+
+        //groups of any size keep every elf and its own line
+        let lines = vec!["1-2,10-12,50-60", "3-4", "55-55,70-80"];
+        let (ranges, line_of) = all_ranges(&lines);
+        assert_eq!(6, ranges.len());
+        assert_eq!(vec![0, 0, 0, 1, 2, 2], line_of);
+        assert_eq!(vec![0, 2], pairs_overlapping_others(&ranges, &line_of));
+    }
+    #[test]
+    fn coverage() {
+        let lines = vec!["2-4,6-8", "12-14,3-3", "20-20,13-15"];
+        let c = Coverage::new(&all_ranges(&lines).0);
+        let show = |rs: &Vec<Range>| rs.iter().map(|r| (r.lower, r.upper)).collect::<Vec<_>>();
+        assert_eq!(vec![(2, 4), (6, 8), (12, 15), (20, 20)], show(&c.covered));
+        assert_eq!(vec![(5, 5), (9, 11), (16, 19)], show(&c.gaps));
+        assert_eq!(0, c.count(1));
+        assert_eq!(1, c.count(2));
+        assert_eq!(2, c.count(3));
+        assert_eq!(0, c.count(10));
+        assert_eq!(2, c.count(13));
+        assert_eq!(1, c.count(20));
+        assert_eq!(0, c.count(21));
+        assert_eq!(vec![(3, 3), (13, 14)], show(&c.crowded(1)));
+        assert_eq!(Vec::<(usize, usize)>::new(), show(&c.crowded(2)));
+        assert_eq!(
+            vec![
+                (2, 2, 1),
+                (3, 3, 2),
+                (4, 4, 1),
+                (6, 8, 1),
+                (12, 12, 1),
+                (13, 14, 2)
+            ],
+            c.counts
+                .iter()
+                .take(6)
+                .map(|(r, n)| (r.lower, r.upper, *n))
+                .collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn coverage_empty() {
+        let c = Coverage::new(&[]);
+        assert!(c.covered.is_empty());
+        assert!(c.gaps.is_empty());
+        assert_eq!(0, c.count(5));
+    }
+    #[test]
+    fn coverage_far_apart() {
+        //sections a long way apart cost no more than any other two ranges
+        let c = Coverage::new(&[Range::new("1-1"), Range::new("9000000000000-9000000000000")]);
+        assert_eq!(2, c.counts.len());
+        assert_eq!(1, c.count(1));
+        assert_eq!(0, c.count(2));
+        assert_eq!(1, c.count(9000000000000));
+        assert_eq!(1, c.gaps.len());
+        assert_eq!(
+            [Range::new("1-1"), Range::new("9000000000000-9000000000000")]
+                .iter()
+                .map(|r| (r.lower, r.upper))
+                .collect::<Vec<_>>(),
+            c.crowded(0)
+                .iter()
+                .map(|r| (r.lower, r.upper))
+                .collect::<Vec<_>>()
+        );
+        //a range running to the very end still counts
+        let c = Coverage::new(&[Range {
+            lower: usize::MAX - 1,
+            upper: usize::MAX,
+        }]);
+        assert_eq!(1, c.count(usize::MAX));
+    }
+    #[test]
+    fn group_from_line() {
+        let g = Group::new("2-3,4-5,1-9");
+        assert_eq!(3, g.ranges.len());
+        assert_eq!(1, g.ranges[2].lower);
+        assert_eq!(9, g.ranges[2].upper);
+        let g = Group::new("7-7");
+        assert_eq!(1, g.ranges.len());
+    }
+    #[test]
+    fn group_predicates() {
+        let g = Group::new("2-3,4-5,1-9");
+        assert!(any_contains(&g));
+        assert!(!common_section(&g));
+        assert!(chained(&g));
+
+        let g = Group::new("2-6,4-8,5-5");
+        assert!(any_contains(&g));
+        assert!(common_section(&g));
+        assert!(chained(&g));
+
+        let g = Group::new("1-2,2-4,4-6,8-9");
+        assert!(!any_contains(&g));
+        assert!(!common_section(&g));
+        assert!(!chained(&g));
+
+        let g = Group::new("4-6");
+        assert!(!any_contains(&g));
+        assert!(common_section(&g));
+        assert!(chained(&g));
+    }
+    #[test]
+    fn group_matches_pair() {
+        //two elves behave exactly like the original Pair rules
+        for line in [
+            "2-4,6-8", "2-3,4-5", "5-7,7-9", "2-8,3-7", "6-6,4-6", "2-6,4-8",
+        ] {
+            let p = Pair::new(line);
+            let g = Group::new(line);
+            assert_eq!(full(&p), any_contains(&g));
+            assert_eq!(partial(&p), common_section(&g));
+            assert_eq!(partial(&p), chained(&g));
+            assert_eq!(g.ranges.len(), Group::from(&p).ranges.len());
+        }
+    }
 }
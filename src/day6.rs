@@ -1,5 +1,32 @@
-use itertools::Itertools;
 use load_file::load_str;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+
+#[derive(Debug, PartialEq)]
+enum StreamError {
+    ZeroWindow,
+    Empty,
+    TooShort { len: usize, window: usize },
+    NoMarker { window: usize },
+}
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::ZeroWindow => write!(f, "window must be at least 1"),
+            StreamError::Empty => write!(f, "stream is empty"),
+            StreamError::TooShort { len, window } => {
+                write!(
+                    f,
+                    "stream of {len} characters is shorter than window {window}"
+                )
+            }
+            StreamError::NoMarker { window } => {
+                write!(f, "no run of {window} different characters in stream")
+            }
+        }
+    }
+}
 
 struct Stream {
     data: Vec<char>,
@@ -7,56 +34,263 @@ struct Stream {
 
 impl Stream {
     pub fn new(s: &str) -> Stream {
-        let data = s.chars().collect::<Vec<char>>();
+        //ignore the trailing newline of a data file
+        let data = s.trim_end().chars().collect::<Vec<char>>();
         Self { data }
     }
-    fn all_different(&self, s: &[char], window: usize) -> bool {
-        //this will remove duplicates
-        let v = s.to_vec().into_iter().unique().collect::<Vec<char>>();
-        //nothing removed?
-        v.len() == window
+
+    pub fn process(&mut self, window: usize) -> Result<usize, StreamError> {
+        if window == 0 {
+            return Err(StreamError::ZeroWindow);
+        }
+        if self.data.is_empty() {
+            return Err(StreamError::Empty);
+        }
+        if self.data.len() < window {
+            return Err(StreamError::TooShort {
+                len: self.data.len(),
+                window,
+            });
+        }
+        self.ends(window, 0)
+            .next()
+            .ok_or(StreamError::NoMarker { window })
+    }
+    // Every position, counted like `process`, where a window of different chars ends.
+    pub fn markers(&self, window: usize) -> Vec<usize> {
+        if window == 0 {
+            return vec![];
+        }
+        self.ends(window, 0).collect()
+    }
+    // Cut the stream at each marker, looking for the next marker only after the
+    // last one so that the marker windows never overlap.
+    pub fn packets(&self, window: usize) -> Vec<Packet> {
+        let mut packets = vec![];
+        if window == 0 {
+            return packets;
+        }
+        let mut from = 0;
+        let mut next = self.ends(window, from).next();
+        while let Some(start) = next {
+            from = start;
+            next = self.ends(window, from).next();
+            let end = next.map_or(self.data.len(), |n| n - window);
+            packets.push(Packet { start, end });
+        }
+        packets
+    }
+    fn ends(&self, window: usize, from: usize) -> impl Iterator<Item = usize> + '_ {
+        //where each char was last seen, data[start..=ix] never has a repeat
+        let mut last_seen: HashMap<char, usize> = HashMap::new();
+        let mut start = from;
+        self.data
+            .iter()
+            .enumerate()
+            .skip(from)
+            .filter_map(move |(ix, ch)| {
+                if let Some(prev) = last_seen.insert(*ch, ix) {
+                    start = start.max(prev + 1);
+                }
+                (ix + 1 - start >= window).then_some(ix + 1)
+            })
     }
+}
+// The chars after one marker up to the beginning of the next marker's window.
+#[derive(Debug, PartialEq)]
+struct Packet {
+    start: usize,
+    end: usize,
+}
+impl Packet {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+// Tracks one window size over bytes pushed one at a time.
+struct Watch {
+    window: usize,
+    seen: usize,
+    start: usize,
+    //1 + the index each byte was last seen at, 0 for never
+    last_seen: [usize; 256],
+}
+impl Watch {
+    pub fn new(window: usize) -> Result<Watch, StreamError> {
+        if window == 0 {
+            return Err(StreamError::ZeroWindow);
+        }
+        Ok(Self {
+            window,
+            seen: 0,
+            start: 0,
+            last_seen: [0; 256],
+        })
+    }
+    // True when the last `window` bytes, ending with this one, are all different.
+    pub fn push(&mut self, byte: u8) -> bool {
+        let prev = self.last_seen[byte as usize];
+        self.start = self.start.max(prev);
+        self.seen += 1;
+        self.last_seen[byte as usize] = self.seen;
+        self.seen - self.start >= self.window
+    }
+}
 
-    pub fn process(&mut self, window: usize) -> usize {
-        let mut found = false;
-        let mut ix = 0;
-        while !found {
-            let slice = &self.data[ix..ix + window];
-            found = self.all_different(slice, window);
-            if !found {
-                ix += 1;
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Event {
+    StartOfPacket(usize),
+    StartOfMessage(usize),
+    //any later position where a packet or message sized window is all different
+    PacketMarker(usize),
+    MessageMarker(usize),
+}
+
+struct MarkerDetector {
+    packet: Watch,
+    message: Watch,
+    packet_found: bool,
+    message_found: bool,
+}
+impl MarkerDetector {
+    pub fn new(packet_window: usize, message_window: usize) -> Result<MarkerDetector, StreamError> {
+        Ok(Self {
+            packet: Watch::new(packet_window)?,
+            message: Watch::new(message_window)?,
+            packet_found: false,
+            message_found: false,
+        })
+    }
+    // Line endings aren't part of the signal so are skipped.
+    pub fn push(&mut self, byte: u8) -> impl Iterator<Item = Event> {
+        let mut events = [None, None];
+        if byte != b'\n' && byte != b'\r' {
+            if self.packet.push(byte) {
+                let at = self.packet.seen;
+                events[0] = Some(if self.packet_found {
+                    Event::PacketMarker(at)
+                } else {
+                    Event::StartOfPacket(at)
+                });
+                self.packet_found = true;
             }
+            if self.message.push(byte) {
+                let at = self.message.seen;
+                events[1] = Some(if self.message_found {
+                    Event::MessageMarker(at)
+                } else {
+                    Event::StartOfMessage(at)
+                });
+                self.message_found = true;
+            }
+        }
+        events.into_iter().flatten()
+    }
+    pub fn feed<'a>(&'a mut self, chunk: &'a [u8]) -> impl Iterator<Item = Event> + 'a {
+        chunk.iter().flat_map(move |b| self.push(*b))
+    }
+    // Reads until end of input, a chunk at a time, so the whole signal is never held.
+    pub fn watch_reader(
+        &mut self,
+        mut r: impl Read,
+        mut on_event: impl FnMut(Event),
+    ) -> io::Result<()> {
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = match r.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.feed(&buf[..n]).for_each(&mut on_event);
         }
-        ix + window
     }
 }
+
 fn part1(s: &str) {
     let mut s = Stream::new(s);
-    let ix = s.process(4);
-    println!("Part 1: {ix}");
+    match s.process(4) {
+        Ok(ix) => println!("Part 1: {ix}"),
+        Err(e) => println!("Part 1: {e}"),
+    }
 }
 fn part2(s: &str) {
     let mut s = Stream::new(s);
-    let ix = s.process(14);
-    println!("Part 2: {ix}");
+    match s.process(14) {
+        Ok(ix) => println!("Part 2: {ix}"),
+        Err(e) => println!("Part 2: {e}"),
+    }
 }
 pub(crate) fn run() {
     let s = load_str!("../data/day6.txt");
     part1(s);
     part2(s);
 }
+pub(crate) fn cli(args: &[String]) {
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["watch"] => {
+            //only report the first markers, every later one would flood the terminal
+            let mut detector = MarkerDetector::new(4, 14).unwrap();
+            detector
+                .watch_reader(io::stdin().lock(), |e| match e {
+                    Event::StartOfPacket(at) => println!("start-of-packet after {at}"),
+                    Event::StartOfMessage(at) => println!("start-of-message after {at}"),
+                    _ => {}
+                })
+                .unwrap();
+        }
+        ["watch", "all"] => {
+            let mut detector = MarkerDetector::new(4, 14).unwrap();
+            detector
+                .watch_reader(io::stdin().lock(), |e| println!("{e:?}"))
+                .unwrap();
+        }
+        ["packets", window] => {
+            let window = window.parse::<usize>().expect("window must be a number");
+            let mut signal = String::new();
+            io::stdin().read_to_string(&mut signal).unwrap();
+            let stream = Stream::new(&signal);
+            println!("{} markers", stream.markers(window).len());
+            stream
+                .packets(window)
+                .iter()
+                .for_each(|p| println!("{:>8}..{:<8} {:>6} chars", p.start, p.end, p.len()));
+        }
+        _ => run(),
+    }
+}
 #[cfg(test)]
 mod tests {
-    use crate::day6::Stream;
-    use load_file::load_str;
+    use crate::day6::{Event, MarkerDetector, Packet, Stream, StreamError};
+    use crate::testutil::Lcg;
+    use itertools::Itertools;
+    use std::time::Instant;
+
+    //the original approach, check every window from scratch
+    fn brute_force(data: &[char], window: usize) -> usize {
+        (0..=data.len() - window)
+            .find(|ix| data[*ix..*ix + window].iter().unique().count() == window)
+            .unwrap()
+            + window
+    }
+    //pseudo random stream over an alphabet of `letters` chars, always ending with a marker
+    fn generate(len: usize, letters: u32, window: usize) -> String {
+        let mut rng = Lcg::new(17);
+        let mut s = (0..len)
+            .map(|_| char::from_u32(0x100 + rng.below(letters)).unwrap())
+            .collect::<String>();
+        s.extend((0..window as u32).map(|c| char::from_u32(0x4000 + c).unwrap()));
+        s
+    }
 
     #[test]
     fn samples_part2() {
         let mut s = Stream::new("mjqjpqmgbljsphdztnvjfqwrcgsmlb");
-        let x = s.process(14);
+        let x = s.process(14).unwrap();
         assert_eq!(19, x);
         let mut s = Stream::new("bvwbjplbgvbhsrlpgdmjqwftvncz");
-        let x = s.process(14);
+        let x = s.process(14).unwrap();
         assert_eq!(23, x);
     }
     #[test]
@@ -67,18 +301,174 @@ mod tests {
         // nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg: first marker after character 10
         // zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw: first marker after character 11
         let mut s = Stream::new("bvwbjplbgvbhsrlpgdmjqwftvncz");
-        let x = s.process(4);
+        let x = s.process(4).unwrap();
         assert_eq!(5, x);
         let mut s = Stream::new("nppdvjthqldpwncqszvftbrmjlhg");
-        let x = s.process(4);
+        let x = s.process(4).unwrap();
         assert_eq!(6, x);
 
         let mut s = Stream::new("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg");
-        let x = s.process(4);
+        let x = s.process(4).unwrap();
         assert_eq!(10, x);
 
         let mut s = Stream::new("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw");
-        let x = s.process(4);
+        let x = s.process(4).unwrap();
         assert_eq!(11, x);
     }
+    #[test]
+    fn matches_brute_force() {
+        for window in [1, 2, 4, 14, 30, 100] {
+            for letters in [window as u32, window as u32 + 3, 2 * window as u32] {
+                let s = generate(3000, letters, window);
+                let data = s.chars().collect::<Vec<_>>();
+                assert_eq!(
+                    brute_force(&data, window),
+                    Stream::new(&s).process(window).unwrap()
+                );
+            }
+        }
+    }
+    #[test]
+    #[ignore = "bench"]
+    fn bench_large_windows() {
+        for window in [4, 14, 100, 500, 1000] {
+            let s = generate(200_000, window as u32, window);
+            let data = s.chars().collect::<Vec<_>>();
+
+            let start = Instant::now();
+            let fast = Stream::new(&s).process(window).unwrap();
+            let fast_time = start.elapsed();
+
+            let start = Instant::now();
+            let slow = brute_force(&data, window);
+            let slow_time = start.elapsed();
+
+            assert_eq!(slow, fast);
+            println!("window {window:>4}: linear {fast_time:?}, window by window {slow_time:?}");
+        }
+    }
+    #[test]
+    fn not_found() {
+        assert_eq!(Err(StreamError::Empty), Stream::new("").process(4));
+        assert_eq!(Err(StreamError::Empty), Stream::new("\n").process(4));
+        assert_eq!(Err(StreamError::ZeroWindow), Stream::new("abcd").process(0));
+        assert_eq!(
+            Err(StreamError::TooShort { len: 3, window: 4 }),
+            Stream::new("abc").process(4)
+        );
+        assert_eq!(
+            Err(StreamError::NoMarker { window: 4 }),
+            Stream::new("abcabcabcabc").process(4)
+        );
+        assert_eq!(Ok(4), Stream::new("abcd\n").process(4));
+        //leading whitespace is part of the signal and keeps its place
+        assert_eq!(Ok(5), Stream::new("  abcd\n").process(4));
+        assert_eq!(Ok(1), Stream::new("aaa").process(1));
+        //the detector refuses the same windows process does
+        assert!(matches!(
+            MarkerDetector::new(0, 14),
+            Err(StreamError::ZeroWindow)
+        ));
+        assert!(matches!(
+            MarkerDetector::new(4, 0),
+            Err(StreamError::ZeroWindow)
+        ));
+    }
+    #[test]
+    fn detector_first_markers() {
+        let samples = [
+            ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+            ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+        ];
+        for (signal, packet, message) in samples {
+            //arriving in awkward sized chunks makes no difference
+            let mut detector = MarkerDetector::new(4, 14).unwrap();
+            let events = signal
+                .as_bytes()
+                .chunks(3)
+                .flat_map(|chunk| detector.feed(chunk).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            assert!(events.contains(&Event::StartOfPacket(packet)));
+            assert!(events.contains(&Event::StartOfMessage(message)));
+            assert_eq!(
+                1,
+                events
+                    .iter()
+                    .filter(|e| matches!(e, Event::StartOfPacket(_)))
+                    .count()
+            );
+        }
+    }
+    #[test]
+    fn detector_later_markers() {
+        let signal = "abcabcdaaxyzw\n";
+        let mut events = vec![];
+        MarkerDetector::new(3, 5)
+            .unwrap()
+            .feed(signal.as_bytes())
+            .for_each(|e| events.push(e));
+        let packets = events
+            .iter()
+            .filter_map(|e| match e {
+                Event::StartOfPacket(at) => Some(*at),
+                Event::PacketMarker(at) => Some(*at),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![3, 4, 5, 6, 7, 8, 11, 12, 13], packets);
+        assert_eq!(
+            vec![Event::StartOfMessage(13)],
+            events
+                .iter()
+                .filter(|e| matches!(e, Event::StartOfMessage(_) | Event::MessageMarker(_)))
+                .copied()
+                .collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn detector_reader() {
+        let signal = "mjqjpqmgbljsphdztnvjfqwrcgsmlb";
+        let mut events = vec![];
+        MarkerDetector::new(4, 14)
+            .unwrap()
+            .watch_reader(signal.as_bytes(), |e| events.push(e))
+            .unwrap();
+        assert_eq!(Event::StartOfPacket(7), events[0]);
+        assert!(events.contains(&Event::StartOfMessage(19)));
+    }
+    #[test]
+    fn all_markers() {
+        let s = Stream::new("abcabcdaaxyzw");
+        assert_eq!(vec![3, 4, 5, 6, 7, 8, 11, 12, 13], s.markers(3));
+        assert_eq!(vec![13], s.markers(5));
+        assert!(s.markers(6).is_empty());
+        assert!(Stream::new("").markers(4).is_empty());
+    }
+    #[test]
+    fn packets() {
+        let s = Stream::new("abcxxdefghhijkzz");
+        //markers abc, xde, fgh and hij leave packets x, nothing, nothing and kzz
+        assert_eq!(
+            vec![
+                Packet { start: 3, end: 4 },
+                Packet { start: 7, end: 7 },
+                Packet { start: 10, end: 10 },
+                Packet { start: 13, end: 16 },
+            ],
+            s.packets(3)
+        );
+        assert_eq!(
+            vec![1, 0, 0, 3],
+            s.packets(3).iter().map(|p| p.len()).collect::<Vec<_>>()
+        );
+        //a marker straight after another gives an empty packet
+        assert_eq!(
+            vec![Packet { start: 2, end: 2 }, Packet { start: 4, end: 4 }],
+            Stream::new("abcd").packets(2)
+        );
+        assert!(Stream::new("aaaa").packets(2).is_empty());
+    }
 }
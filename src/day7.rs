@@ -1,105 +1,1343 @@
 use indextree::{Arena, NodeId};
 use load_file::load_str;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Write};
 
-#[derive(Clone)]
-pub struct Entry<'a> {
-    name: &'a str,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Dir,
+    File,
+}
+
+// A file's size is its own, a directory's is the cached total of everything below it.
+// Names are owned so a tree can outlive, and be sent away from, the text it came from.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    name: String,
+    kind: Kind,
     size: u32,
 }
 
-type Input<'a> = Arena<Entry<'a>>;
+type Input = Arena<Entry>;
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    UnknownDir(String),
+    AboveRoot,
+    UnknownCommand(String),
+    BadSize(String),
+    BadListing(String),
+    ListingWithoutLs,
+    //a directory's total would pass u32::MAX
+    TooLarge(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LineError {
+    line: usize,
+    error: ParseError,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match &self.error {
+            ParseError::UnknownDir(dir) => format!("cd into unknown directory {dir}"),
+            ParseError::AboveRoot => "cd .. from the root".to_string(),
+            ParseError::UnknownCommand(cmd) => format!("unknown command {cmd}"),
+            ParseError::BadSize(size) => format!("bad file size {size}"),
+            ParseError::BadListing(l) => format!("expected 'dir name' or 'size name', got {l}"),
+            ParseError::ListingWithoutLs => "listing output before any ls".to_string(),
+            ParseError::TooLarge(name) => format!("{name} makes a directory too large"),
+        };
+        write!(f, "line {}: {reason}", self.line)
+    }
+}
 
-fn parse(input: &str) -> Input {
+fn parse(input: &str) -> Result<Input, LineError> {
     let mut arena = Arena::new();
-    let mut current_id = arena.new_node(Entry { name: "/", size: 0 });
-
-    input
-        .split("$ ")
-        .skip(2)
-        .map(|chunk| {
-            let (cmd, rest) = chunk.split_at(2);
-            (cmd, rest.trim())
-        })
-        .try_for_each(|cmd| {
-            match cmd {
-                ("cd", "..") => {
-                    current_id = arena.get(current_id)?.parent()?;
+    let root = arena.new_node(Entry {
+        name: "/".to_string(),
+        kind: Kind::Dir,
+        size: 0,
+    });
+    let mut current_id = root;
+    let mut listing = false;
+
+    for (ix, line) in input.lines().enumerate() {
+        let fail = |error| LineError {
+            line: ix + 1,
+            error,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(cmd) = line.strip_prefix("$ ") {
+            listing = false;
+            match cmd.split_once(' ') {
+                Some(("cd", "/")) => current_id = root,
+                Some(("cd", "..")) => {
+                    current_id = arena[current_id]
+                        .parent()
+                        .ok_or_else(|| fail(ParseError::AboveRoot))?;
                 }
-                ("cd", dir) => {
+                Some(("cd", dir)) => {
                     current_id = current_id
                         .children(&arena)
-                        .find(|id| arena.get(*id).unwrap().get().name == dir)?;
-                }
-                ("ls", rest) => {
-                    rest.lines().try_for_each(|l| {
-                        let (size, name) = l.split_once(' ')?;
-                        if size == "dir" {
-                            let id = arena.new_node(Entry { name, size: 0 });
-                            current_id.append(id, &mut arena);
-                        } else {
-                            let size = size.parse::<u32>().ok()?;
-                            current_id
-                                .ancestors(&arena)
-                                .collect::<Vec<NodeId>>()
-                                .into_iter()
-                                .for_each(|id| {
-                                    arena.get_mut(id).unwrap().get_mut().size += size;
-                                })
-                        }
-                        Some(())
-                    });
+                        .find(|id| {
+                            arena[*id].get().name == dir && arena[*id].get().kind == Kind::Dir
+                        })
+                        .ok_or_else(|| fail(ParseError::UnknownDir(dir.to_string())))?;
                 }
-                _ => unreachable!(),
+                None if cmd == "ls" => listing = true,
+                _ => return Err(fail(ParseError::UnknownCommand(cmd.to_string()))),
             }
+            continue;
+        }
+        if !listing {
+            return Err(fail(ParseError::ListingWithoutLs));
+        }
+        let (size, name) = line
+            .split_once(' ')
+            .ok_or_else(|| fail(ParseError::BadListing(line.to_string())))?;
+        //listing the same directory twice mustn't count its files twice
+        if current_id
+            .children(&arena)
+            .any(|id| arena[id].get().name == name)
+        {
+            continue;
+        }
+        if size == "dir" {
+            let id = arena.new_node(Entry {
+                name: name.to_string(),
+                kind: Kind::Dir,
+                size: 0,
+            });
+            current_id.append(id, &mut arena);
+        } else {
+            let size = size
+                .parse::<u32>()
+                .map_err(|_| fail(ParseError::BadSize(size.to_string())))?;
+            let id = arena.new_node(Entry {
+                name: name.to_string(),
+                kind: Kind::File,
+                size,
+            });
+            current_id.append(id, &mut arena);
+            grow(&mut arena, current_id, size)
+                .ok_or_else(|| fail(ParseError::TooLarge(name.to_string())))?;
+        }
+    }
 
-            Some(())
-        });
+    Ok(arena)
+}
+
+// Keep the cached directory totals right from `dir` up to the root. None, with
+// nothing changed, if some total would no longer fit.
+fn grow(arena: &mut Input, dir: NodeId, size: u32) -> Option<()> {
+    let ancestors = dir.ancestors(arena).collect::<Vec<NodeId>>();
+    let totals = ancestors
+        .iter()
+        .map(|id| arena[*id].get().size.checked_add(size))
+        .collect::<Option<Vec<_>>>()?;
+    ancestors
+        .into_iter()
+        .zip(totals)
+        .for_each(|(id, total)| arena[id].get_mut().size = total);
+    Some(())
+}
+fn shrink(arena: &mut Input, dir: NodeId, size: u32) {
+    dir.ancestors(arena)
+        .collect::<Vec<NodeId>>()
+        .into_iter()
+        .for_each(|id| arena[id].get_mut().size -= size);
+}
+
+fn root(arena: &Input) -> Option<NodeId> {
+    arena.iter().next().and_then(|node| arena.get_node_id(node))
+}
+
+// Follows a path like /a/e from the root.
+#[cfg(test)]
+fn find(arena: &Input, path: &str) -> Option<NodeId> {
+    path.split('/')
+        .filter(|part| !part.is_empty())
+        .try_fold(root(arena)?, |id, part| {
+            id.children(arena)
+                .find(|child| arena[*child].get().name == part)
+        })
+}
+
+// The files directly inside a directory.
+#[cfg(test)]
+fn files(arena: &Input, dir: NodeId) -> impl Iterator<Item = &Entry> {
+    dir.children(arena)
+        .map(|id| arena[id].get())
+        .filter(|entry| entry.kind == Kind::File)
+}
 
+fn largest_file(arena: &Input) -> Option<&Entry> {
     arena
+        .iter()
+        .filter(|node| !node.is_removed())
+        .map(|node| node.get())
+        .filter(|entry| entry.kind == Kind::File)
+        .max_by_key(|entry| entry.size)
+}
+
+// Names that can't be told apart from a path, or from the next line of a transcript.
+fn bad_name(name: &str) -> bool {
+    name.is_empty()
+        || name.trim() != name
+        || name.contains(['/', '\n', '\r'])
+        || name == "."
+        || name == ".."
+}
+
+// Absolute path of a node, eg /a/e
+fn path(arena: &Input, id: NodeId) -> String {
+    let mut names = id
+        .ancestors(arena)
+        .map(|id| arena[id].get().name.as_ref())
+        .collect::<Vec<_>>();
+    names.pop();
+    names.reverse();
+    format!("/{}", names.join("/"))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortBy {
+    Name,
+    //largest first
+    Size,
+}
+
+// Rendering options shared by `tree` and `du`.
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    sort: SortBy,
+    human: bool,
+}
+
+impl Layout {
+    fn size(&self, size: u32) -> String {
+        if self.human {
+            human(size)
+        } else {
+            size.to_string()
+        }
+    }
+    fn children(&self, arena: &Input, id: NodeId) -> Vec<NodeId> {
+        let mut children = id.children(arena).collect::<Vec<_>>();
+        match self.sort {
+            SortBy::Name => {
+                children.sort_by(|a, b| arena[*a].get().name.cmp(&arena[*b].get().name))
+            }
+            SortBy::Size => children.sort_by_key(|id| std::cmp::Reverse(arena[*id].get().size)),
+        }
+        children
+    }
+}
+
+// Sizes the way du -h shows them, powers of 1024 rounded up, one decimal below 10.
+fn human(size: u32) -> String {
+    let mut value = size as f64;
+    let mut units = ["", "K", "M", "G", "T"].iter();
+    let mut unit = units.next().unwrap();
+    while value >= 1024.0 {
+        value /= 1024.0;
+        unit = units.next().unwrap();
+    }
+    if unit.is_empty() {
+        size.to_string()
+    } else if value < 10.0 {
+        format!("{:.1}{unit}", (value * 10.0).ceil() / 10.0)
+    } else {
+        format!("{}{unit}", value.ceil())
+    }
+}
+
+// Indented like Unix tree, every entry with its size.
+fn tree(arena: &Input, layout: Layout) -> String {
+    fn walk(arena: &Input, layout: Layout, id: NodeId, prefix: &str, out: &mut String) {
+        let children = layout.children(arena, id);
+        for (ix, child) in children.iter().enumerate() {
+            let last = ix == children.len() - 1;
+            let entry = arena[*child].get();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            out.push_str(&format!(
+                "{prefix}{branch}[{}]  {}\n",
+                layout.size(entry.size),
+                entry.name
+            ));
+            if entry.kind == Kind::Dir {
+                walk(arena, layout, *child, &format!("{prefix}{indent}"), out);
+            }
+        }
+    }
+    let mut out = String::new();
+    if let Some(root) = root(arena) {
+        out.push_str(&format!("[{}]  /\n", layout.size(arena[root].get().size)));
+        walk(arena, layout, root, "", &mut out);
+    }
+    out
+}
+
+// Like du --max-depth=N, each directory after its subdirectories.
+fn du(arena: &Input, from: NodeId, max_depth: usize, layout: Layout) -> String {
+    fn walk(arena: &Input, layout: Layout, id: NodeId, depth: usize, max: usize, out: &mut String) {
+        for child in layout.children(arena, id) {
+            if arena[child].get().kind == Kind::Dir {
+                walk(arena, layout, child, depth + 1, max, out);
+            }
+        }
+        if depth <= max {
+            let size = layout.size(arena[id].get().size);
+            out.push_str(&format!("{size}\t{}\n", path(arena, id)));
+        }
+    }
+    let mut out = String::new();
+    walk(arena, layout, from, 0, max_depth, &mut out);
+    out
+}
+
+fn depth(arena: &Input, id: NodeId) -> usize {
+    id.ancestors(arena).count() - 1
+}
+
+// Shell style glob over whole paths. * and ? stay inside one path component, ** crosses them.
+fn glob(pattern: &str, path: &str) -> bool {
+    fn matches(p: &[u8], s: &[u8]) -> bool {
+        match p {
+            [] => s.is_empty(),
+            [b'*', b'*', rest @ ..] => (0..=s.len()).any(|ix| matches(rest, &s[ix..])),
+            [b'*', rest @ ..] => (0..=s.len())
+                .take_while(|ix| *ix == 0 || s[ix - 1] != b'/')
+                .any(|ix| matches(rest, &s[ix..])),
+            [b'?', rest @ ..] => !s.is_empty() && s[0] != b'/' && matches(rest, &s[1..]),
+            [c, rest @ ..] => s.first() == Some(c) && matches(rest, &s[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), path.as_bytes())
+}
+
+// Every condition given has to hold. Sizes and depths are exclusive bounds.
+#[derive(Debug, Default, PartialEq)]
+pub struct Query {
+    kind: Option<Kind>,
+    smaller_than: Option<u32>,
+    larger_than: Option<u32>,
+    shallower_than: Option<usize>,
+    deeper_than: Option<usize>,
+    path: Option<String>,
+}
+
+impl Query {
+    // Terms like: dirs, files, size<100000, size>10, depth>2, depth<4, path=/a/**
+    pub fn parse(terms: &[&str]) -> Result<Query, String> {
+        let mut q = Query::default();
+        for term in terms {
+            let number = |n: &str| n.parse().map_err(|_| format!("bad number in {term}"));
+            match *term {
+                "dirs" => q.kind = Some(Kind::Dir),
+                "files" => q.kind = Some(Kind::File),
+                "all" => q.kind = None,
+                t if t.starts_with("size<") => q.smaller_than = Some(number(&t[5..])?),
+                t if t.starts_with("size>") => q.larger_than = Some(number(&t[5..])?),
+                t if t.starts_with("depth<") => q.shallower_than = Some(number(&t[6..])? as usize),
+                t if t.starts_with("depth>") => q.deeper_than = Some(number(&t[6..])? as usize),
+                t if t.starts_with("path=") => q.path = Some(t[5..].to_string()),
+                _ => return Err(format!("unknown query term {term}")),
+            }
+        }
+        Ok(q)
+    }
+    pub fn matches(&self, arena: &Input, id: NodeId) -> bool {
+        let entry = arena[id].get();
+        let d = depth(arena, id);
+        self.kind.is_none_or(|k| k == entry.kind)
+            && self.smaller_than.is_none_or(|n| entry.size < n)
+            && self.larger_than.is_none_or(|n| entry.size > n)
+            && self.shallower_than.is_none_or(|n| d < n)
+            && self.deeper_than.is_none_or(|n| d > n)
+            && self.path.as_ref().is_none_or(|p| glob(p, &path(arena, id)))
+    }
+    pub fn select(&self, arena: &Input) -> Vec<NodeId> {
+        root(arena).map_or(vec![], |root| self.select_under(arena, root))
+    }
+    pub fn select_under(&self, arena: &Input, from: NodeId) -> Vec<NodeId> {
+        from.descendants(arena)
+            .filter(|id| self.matches(arena, *id))
+            .collect()
+    }
+}
+
+// The smallest directory, other than the root, whose removal leaves `required`
+// free on a disk of size `disk`. None if there's room already, or no one directory
+// is big enough.
+fn smallest_freeing(arena: &Input, disk: u32, required: u32) -> Option<NodeId> {
+    let root = root(arena)?;
+    let free = disk.saturating_sub(arena[root].get().size);
+    if free >= required {
+        return None;
+    }
+    let needed = required - free;
+    root.descendants(arena)
+        .skip(1)
+        .filter(|id| arena[*id].get().kind == Kind::Dir && arena[*id].get().size >= needed)
+        .min_by_key(|id| arena[*id].get().size)
 }
 
+const SMALL_DIR: u32 = 100000;
+const DISK: u32 = 70000000;
+const UPDATE: u32 = 30000000;
+
 pub fn part1(arena: &Input) -> Option<u32> {
+    let small = Query {
+        kind: Some(Kind::Dir),
+        smaller_than: Some(SMALL_DIR),
+        ..Query::default()
+    };
     Some(
-        arena
+        small
+            .select(arena)
             .iter()
-            .map(|entry| entry.get().size)
-            .filter(|size| *size < 100000)
+            .map(|id| arena[*id].get().size)
             .sum(),
     )
 }
 
 pub fn part2(arena: &Input) -> Option<u32> {
-    let mut values = arena.iter().map(|entry| entry.get().size);
-    let total_size = values.next()?;
-    let needed = total_size + 30000000 - 70000000;
-    values.filter(|x| *x >= needed).min()
+    smallest_freeing(arena, DISK, UPDATE).map(|id| arena[id].get().size)
 }
 
 pub(crate) fn run() {
     let s = load_str!("../data/day7.txt");
-    let inp = parse(s);
+    let inp = match parse(s) {
+        Ok(inp) => inp,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
     println!("{:?}", part1(&inp).unwrap());
 
     part2(&inp);
     println!("{:?}", part2(&inp).unwrap());
+    if let Some(file) = largest_file(&inp) {
+        println!("Largest file {} {}", file.name, file.size);
+    }
+}
+// Nested form of the filesystem for JSON. Directory sizes are written out
+// for reading but worked out again from the files when loading.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Node {
+    Dir {
+        name: String,
+        size: u32,
+        children: Vec<Node>,
+    },
+    File {
+        name: String,
+        size: u32,
+    },
+}
+
+impl Node {
+    fn from_arena(arena: &Input, id: NodeId) -> Node {
+        let entry = arena[id].get();
+        let by_name = Layout {
+            sort: SortBy::Name,
+            human: false,
+        };
+        match entry.kind {
+            Kind::Dir => Node::Dir {
+                name: entry.name.to_string(),
+                size: entry.size,
+                children: by_name
+                    .children(arena, id)
+                    .iter()
+                    .map(|child| Node::from_arena(arena, *child))
+                    .collect(),
+            },
+            Kind::File => Node::File {
+                name: entry.name.to_string(),
+                size: entry.size,
+            },
+        }
+    }
+    // Refuses anything a transcript couldn't give back unchanged.
+    fn add_to(self, arena: &mut Input, parent: NodeId) -> Result<(), JsonError> {
+        let (name, kind, size) = match &self {
+            Node::Dir { name, .. } => (name.clone(), Kind::Dir, 0),
+            Node::File { name, size } => (name.clone(), Kind::File, *size),
+        };
+        let dir = |arena: &Input| path(arena, parent);
+        if bad_name(&name) {
+            return Err(JsonError::BadName {
+                dir: dir(arena),
+                name,
+            });
+        }
+        if parent
+            .children(arena)
+            .any(|id| arena[id].get().name == name)
+        {
+            return Err(JsonError::Duplicate {
+                dir: dir(arena),
+                name,
+            });
+        }
+        if grow(arena, parent, size).is_none() {
+            return Err(JsonError::TooLarge {
+                dir: dir(arena),
+                name,
+            });
+        }
+        let id = arena.new_node(Entry { name, kind, size });
+        parent.append(id, arena);
+        if let Node::Dir { children, .. } = self {
+            children
+                .into_iter()
+                .try_for_each(|child| child.add_to(arena, id))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum JsonError {
+    Syntax(serde_json::Error),
+    RootIsFile,
+    BadName { dir: String, name: String },
+    Duplicate { dir: String, name: String },
+    //a directory's total would pass u32::MAX
+    TooLarge { dir: String, name: String },
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::Syntax(e) => write!(f, "{e}"),
+            JsonError::RootIsFile => write!(f, "the top level must be a dir"),
+            JsonError::BadName { dir, name } => write!(f, "bad name {name:?} in {dir}"),
+            JsonError::Duplicate { dir, name } => write!(f, "{name} appears twice in {dir}"),
+            JsonError::TooLarge { dir, name } => {
+                write!(f, "{name} makes {dir} or a parent too large")
+            }
+        }
+    }
+}
+
+fn to_json(arena: &Input) -> String {
+    let root = Node::from_arena(arena, root(arena).unwrap());
+    serde_json::to_string_pretty(&root).unwrap()
+}
+
+fn from_json(json: &str) -> Result<Input, JsonError> {
+    let mut arena = Arena::new();
+    let root = arena.new_node(Entry {
+        name: "/".to_string(),
+        kind: Kind::Dir,
+        size: 0,
+    });
+    //the top level is the root directory itself
+    match serde_json::from_str(json).map_err(JsonError::Syntax)? {
+        Node::Dir { children, .. } => children
+            .into_iter()
+            .try_for_each(|child| child.add_to(&mut arena, root))?,
+        Node::File { .. } => return Err(JsonError::RootIsFile),
+    }
+    Ok(arena)
+}
+
+// A $ cd / $ ls transcript that parses back into the same tree, visiting
+// directories depth first in name order.
+fn transcript(arena: &Input) -> String {
+    fn walk(arena: &Input, id: NodeId, out: &mut String) {
+        let by_name = Layout {
+            sort: SortBy::Name,
+            human: false,
+        };
+        let children = by_name.children(arena, id);
+        out.push_str("$ ls\n");
+        children.iter().for_each(|child| {
+            let e = arena[*child].get();
+            match e.kind {
+                Kind::Dir => out.push_str(&format!("dir {}\n", e.name)),
+                Kind::File => out.push_str(&format!("{} {}\n", e.size, e.name)),
+            }
+        });
+        children
+            .iter()
+            .filter(|child| arena[**child].get().kind == Kind::Dir)
+            .for_each(|child| {
+                out.push_str(&format!("$ cd {}\n", arena[*child].get().name));
+                walk(arena, *child, out);
+                out.push_str("$ cd ..\n");
+            });
+    }
+    let mut out = "$ cd /\n".to_string();
+    if let Some(root) = root(arena) {
+        walk(arena, root, &mut out);
+    }
+    out
+}
+
+// A shell over the reconstructed filesystem, to try out changes like deleting a directory.
+struct Shell {
+    arena: Input,
+    cwd: NodeId,
+}
+
+impl Shell {
+    pub fn new(arena: Input) -> Shell {
+        let cwd = root(&arena).unwrap();
+        Self { arena, cwd }
+    }
+    // Absolute, or relative to the working directory, with . and .. allowed.
+    fn resolve(&self, p: &str) -> Option<NodeId> {
+        let start = if p.starts_with('/') {
+            root(&self.arena)?
+        } else {
+            self.cwd
+        };
+        p.split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .try_fold(start, |id, part| match part {
+                ".." => Some(self.arena[id].parent().unwrap_or(id)),
+                _ => id
+                    .children(&self.arena)
+                    .find(|child| self.arena[*child].get().name == part),
+            })
+    }
+    fn dir(&self, p: &str) -> Result<NodeId, String> {
+        let id = self
+            .resolve(p)
+            .ok_or(format!("{p}: no such file or directory"))?;
+        match self.arena[id].get().kind {
+            Kind::Dir => Ok(id),
+            Kind::File => Err(format!("{p}: not a directory")),
+        }
+    }
+    fn create(&mut self, name: &str, kind: Kind, size: u32) -> Result<(), String> {
+        if bad_name(name) {
+            return Err(format!("{name}: bad name"));
+        }
+        if self.resolve(name).is_some() {
+            return Err(format!("{name}: already exists"));
+        }
+        grow(&mut self.arena, self.cwd, size).ok_or(format!("no room for {name}"))?;
+        let id = self.arena.new_node(Entry {
+            name: name.to_string(),
+            kind,
+            size,
+        });
+        self.cwd.append(id, &mut self.arena);
+        Ok(())
+    }
+    fn remove(&mut self, p: &str) -> Result<(), String> {
+        let id = self
+            .resolve(p)
+            .ok_or(format!("{p}: no such file or directory"))?;
+        let parent = self.arena[id]
+            .parent()
+            .ok_or("can't remove the root".to_string())?;
+        //don't leave the working directory inside what was removed
+        if self.cwd.ancestors(&self.arena).any(|a| a == id) {
+            self.cwd = parent;
+        }
+        let size = self.arena[id].get().size;
+        shrink(&mut self.arena, parent, size);
+        id.remove_subtree(&mut self.arena);
+        Ok(())
+    }
+    // Runs one command line and returns what it prints.
+    pub fn exec(&mut self, line: &str) -> Result<String, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let layout = Layout {
+            sort: SortBy::Name,
+            human: false,
+        };
+        match words[..] {
+            [] => Ok(String::new()),
+            ["pwd"] => Ok(format!("{}\n", path(&self.arena, self.cwd))),
+            ["cd"] => {
+                self.cwd = root(&self.arena).unwrap();
+                Ok(String::new())
+            }
+            ["cd", p] => {
+                self.cwd = self.dir(p)?;
+                Ok(String::new())
+            }
+            ["ls"] | ["ls", _] => {
+                let dir = self.dir(words.get(1).unwrap_or(&"."))?;
+                Ok(layout
+                    .children(&self.arena, dir)
+                    .iter()
+                    .map(|id| {
+                        let e = self.arena[*id].get();
+                        match e.kind {
+                            Kind::Dir => format!("dir {}\n", e.name),
+                            Kind::File => format!("{} {}\n", e.size, e.name),
+                        }
+                    })
+                    .collect())
+            }
+            ["du", ref rest @ ..] => {
+                let human = rest.contains(&"-h");
+                let p = rest.iter().find(|w| !w.starts_with('-')).unwrap_or(&".");
+                let layout = Layout { human, ..layout };
+                Ok(du(&self.arena, self.dir(p)?, usize::MAX, layout))
+            }
+            ["find", ref rest @ ..] => {
+                //an optional starting directory then query terms
+                let (from, terms) = match rest.first() {
+                    Some(p) if p.starts_with('/') || p.starts_with('.') => {
+                        (self.dir(p)?, &rest[1..])
+                    }
+                    _ => (self.cwd, rest),
+                };
+                let q = Query::parse(terms)?;
+                Ok(q.select_under(&self.arena, from)
+                    .iter()
+                    .map(|id| format!("{}\n", path(&self.arena, *id)))
+                    .collect())
+            }
+            ["rm", p] => self.remove(p).map(|_| String::new()),
+            ["mkdir", name] => self.create(name, Kind::Dir, 0).map(|_| String::new()),
+            ["touch", name] => self.create(name, Kind::File, 0).map(|_| String::new()),
+            ["touch", name, size] => {
+                let size = size.parse().map_err(|_| format!("bad size {size}"))?;
+                self.create(name, Kind::File, size).map(|_| String::new())
+            }
+            ["df"] | ["df", _, _] => {
+                let disk = words
+                    .get(1)
+                    .map_or(Ok(DISK), |d| d.parse())
+                    .map_err(|_| "bad disk size")?;
+                let need = words
+                    .get(2)
+                    .map_or(Ok(UPDATE), |d| d.parse())
+                    .map_err(|_| "bad size")?;
+                let used = self.arena[root(&self.arena).unwrap()].get().size;
+                let free = disk.saturating_sub(used);
+                let advice = if free >= need {
+                    "nothing to remove".to_string()
+                } else {
+                    smallest_freeing(&self.arena, disk, need)
+                        .map_or("no single directory is big enough".to_string(), |id| {
+                            format!("remove {}", path(&self.arena, id))
+                        })
+                };
+                Ok(format!(
+                    "used {used} free {free} of {disk}, to have {need} free {advice}\n"
+                ))
+            }
+            _ => Err(format!("{line}: unknown command")),
+        }
+    }
+}
+
+// Commands from stdin, one per line, with a prompt when it's a terminal.
+fn shell(arena: Input) {
+    let mut sh = Shell::new(arena);
+    let interactive = io::stdin().is_terminal();
+    let prompt = |sh: &Shell| {
+        if interactive {
+            print!("{} $ ", path(&sh.arena, sh.cwd));
+            io::stdout().flush().unwrap();
+        }
+    };
+    prompt(&sh);
+    for line in io::stdin().lock().lines() {
+        let line = line.unwrap();
+        if line.trim() == "exit" {
+            break;
+        }
+        match sh.exec(&line) {
+            Ok(out) => print!("{out}"),
+            Err(e) => println!("{e}"),
+        }
+        prompt(&sh);
+    }
+}
+
+pub(crate) fn cli(args: &[String]) {
+    let s = load_str!("../data/day7.txt");
+    let inp = match parse(s) {
+        Ok(inp) => inp,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+    let layout = Layout {
+        sort: if args.iter().any(|a| a == "--sort=size") {
+            SortBy::Size
+        } else {
+            SortBy::Name
+        },
+        human: args.iter().any(|a| a == "-h"),
+    };
+    match args.first().map(String::as_str) {
+        Some("tree") => print!("{}", tree(&inp, layout)),
+        Some("du") => {
+            let max_depth = args
+                .iter()
+                .find_map(|a| a.strip_prefix("--max-depth="))
+                .map_or(usize::MAX, |d| {
+                    d.parse().expect("max depth must be a number")
+                });
+            print!("{}", du(&inp, root(&inp).unwrap(), max_depth, layout));
+        }
+        Some("query") => {
+            let terms = args[1..].iter().map(String::as_str).collect::<Vec<_>>();
+            match Query::parse(&terms) {
+                Ok(q) => {
+                    let found = q.select(&inp);
+                    found.iter().for_each(|id| {
+                        println!("{}\t{}", layout.size(inp[*id].get().size), path(&inp, *id))
+                    });
+                    println!("{} matches", found.len());
+                }
+                Err(e) => println!("{e}"),
+            }
+        }
+        Some("shell") => shell(inp),
+        Some("json") => println!("{}", to_json(&inp)),
+        Some("transcript") => match args.get(1) {
+            Some(file) => {
+                let json = std::fs::read_to_string(file).unwrap();
+                match from_json(&json) {
+                    Ok(arena) => print!("{}", transcript(&arena)),
+                    Err(e) => println!("{file}: {e}"),
+                }
+            }
+            None => print!("{}", transcript(&inp)),
+        },
+        Some("free") => {
+            let value = |name: &str, default: u32| {
+                args.iter()
+                    .find_map(|a| a.strip_prefix(name))
+                    .map_or(default, |v| v.parse().expect("sizes must be numbers"))
+            };
+            let disk = value("--disk=", DISK);
+            let required = value("--need=", UPDATE);
+            let free = disk.saturating_sub(inp[root(&inp).unwrap()].get().size);
+            match smallest_freeing(&inp, disk, required) {
+                _ if free >= required => println!("{free} already free, nothing to remove"),
+                Some(id) => println!("{}\t{}", layout.size(inp[id].get().size), path(&inp, id)),
+                None => println!("no single directory frees enough space"),
+            }
+        }
+        _ => run(),
+    }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
-    use load_file::load_str;
+    use crate::testutil::Lcg;
+
+    const SAMPLE: &str = r#"$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k"#;
 
     #[test]
     fn test_part_one() {
-        let s = load_str!("../data/day7.txt");
-
-        let result = part1(&parse(s));
+        let result = part1(&parse(SAMPLE).unwrap());
         assert_eq!(result, Some(95437));
     }
 
     #[test]
     fn test_part_two() {
-        let s = load_str!("../data/day7.txt");
-        let result = part2(&parse(s));
+        let result = part2(&parse(SAMPLE).unwrap());
         assert_eq!(result, Some(24933642));
     }
+
+    #[test]
+    fn keeps_files() {
+        let arena = parse(SAMPLE).unwrap();
+        let e = find(&arena, "/a/e").unwrap();
+        let names = files(&arena, e)
+            .map(|f| (f.name.as_ref(), f.size))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("i", 584)], names);
+        assert_eq!(584, arena[e].get().size);
+        assert_eq!(94853, arena[find(&arena, "/a").unwrap()].get().size);
+        assert_eq!(48381165, arena[find(&arena, "/").unwrap()].get().size);
+        assert_eq!(Kind::File, arena[find(&arena, "/d/k").unwrap()].get().kind);
+        assert!(find(&arena, "/a/x").is_none());
+
+        let largest = largest_file(&arena).unwrap();
+        assert_eq!(("b.txt", 14848514), (largest.name.as_ref(), largest.size));
+    }
+
+    #[test]
+    fn repeated_cd_root_and_ls() {
+        //going back to the root and listing again only adds the new file
+        let input = format!("{SAMPLE}\n$ cd /\n$ ls\ndir a\n14848514 b.txt\n$ cd a\n$ ls\n10 new");
+        let arena = parse(&input).unwrap();
+        let a = find(&arena, "/a").unwrap();
+        assert_eq!(4, files(&arena, a).count());
+        assert_eq!(94863, arena[a].get().size);
+        assert_eq!(48381175, arena[find(&arena, "/").unwrap()].get().size);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |input| parse(input).err().unwrap();
+        assert_eq!(
+            LineError {
+                line: 3,
+                error: ParseError::UnknownDir("x".to_string())
+            },
+            err("$ cd /\n$ ls\n$ cd x")
+        );
+        assert_eq!(
+            LineError {
+                line: 2,
+                error: ParseError::AboveRoot
+            },
+            err("$ cd /\n$ cd ..")
+        );
+        assert_eq!(
+            LineError {
+                line: 1,
+                error: ParseError::UnknownCommand("rm -rf".to_string())
+            },
+            err("$ rm -rf")
+        );
+        assert_eq!(
+            LineError {
+                line: 3,
+                error: ParseError::BadSize("12k".to_string())
+            },
+            err("$ cd /\n$ ls\n12k a.txt")
+        );
+        assert_eq!(
+            LineError {
+                line: 2,
+                error: ParseError::BadListing("junk".to_string())
+            },
+            err("$ ls\njunk")
+        );
+        assert_eq!(
+            LineError {
+                line: 2,
+                error: ParseError::ListingWithoutLs
+            },
+            err("$ cd /\n12 a.txt")
+        );
+        assert_eq!(
+            "line 3: cd into unknown directory x",
+            err("$ cd /\n$ ls\n$ cd x").to_string()
+        );
+        //totals that won't fit a u32 are refused rather than wrapped
+        assert_eq!(
+            LineError {
+                line: 4,
+                error: ParseError::TooLarge("b".to_string())
+            },
+            err("$ ls\ndir d\n4294967295 a\n1 b")
+        );
+        assert_eq!(
+            LineError {
+                line: 6,
+                error: ParseError::TooLarge("y".to_string())
+            },
+            err("$ ls\n4294967290 x\ndir d\n$ cd d\n$ ls\n6 y")
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let arena = parse(SAMPLE).unwrap();
+        assert_eq!("/", path(&arena, find(&arena, "/").unwrap()));
+        assert_eq!("/a/e", path(&arena, find(&arena, "/a/e").unwrap()));
+        assert_eq!("/d/k", path(&arena, find(&arena, "/d/k").unwrap()));
+    }
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!("584", human(584));
+        assert_eq!("1023", human(1023));
+        assert_eq!("1.0K", human(1024));
+        assert_eq!("1.5K", human(1536));
+        assert_eq!("93K", human(94853));
+        assert_eq!("47M", human(48381165));
+        assert_eq!("7.7M", human(8033020));
+    }
+
+    #[test]
+    fn render_tree() {
+        let arena = parse(SAMPLE).unwrap();
+        let by_name = Layout {
+            sort: SortBy::Name,
+            human: false,
+        };
+        let expected = r#"[48381165]  /
+├── [94853]  a
+│   ├── [584]  e
+│   │   └── [584]  i
+│   ├── [29116]  f
+│   ├── [2557]  g
+│   └── [62596]  h.lst
+├── [14848514]  b.txt
+├── [8504156]  c.dat
+└── [24933642]  d
+    ├── [5626152]  d.ext
+    ├── [8033020]  d.log
+    ├── [4060174]  j
+    └── [7214296]  k
+"#;
+        assert_eq!(expected, tree(&arena, by_name));
+
+        let by_size = Layout {
+            sort: SortBy::Size,
+            human: true,
+        };
+        let first = tree(&arena, by_size)
+            .lines()
+            .take(3)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!("[47M]  /\n├── [24M]  d\n│   ├── [7.7M]  d.log", first);
+    }
+
+    #[test]
+    fn render_du() {
+        let arena = parse(SAMPLE).unwrap();
+        let layout = Layout {
+            sort: SortBy::Name,
+            human: false,
+        };
+        assert_eq!(
+            "584\t/a/e\n94853\t/a\n24933642\t/d\n48381165\t/\n",
+            du(&arena, root(&arena).unwrap(), usize::MAX, layout)
+        );
+        assert_eq!(
+            "94853\t/a\n24933642\t/d\n48381165\t/\n",
+            du(&arena, root(&arena).unwrap(), 1, layout)
+        );
+        assert_eq!(
+            "48381165\t/\n",
+            du(&arena, root(&arena).unwrap(), 0, layout)
+        );
+        let layout = Layout {
+            sort: SortBy::Size,
+            human: true,
+        };
+        assert_eq!(
+            "24M\t/d\n93K\t/a\n47M\t/\n",
+            du(&arena, root(&arena).unwrap(), 1, layout)
+        );
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob("/a/*", "/a/e"));
+        assert!(!glob("/a/*", "/a/e/i"));
+        assert!(glob("/a/**", "/a/e/i"));
+        assert!(glob("**.dat", "/c.dat"));
+        assert!(glob("/*/?", "/a/e"));
+        assert!(!glob("/*/?", "/a/h.lst"));
+        assert!(glob("/d/d.*", "/d/d.log"));
+        assert!(!glob("/d", "/d/k"));
+    }
+
+    #[test]
+    fn queries() {
+        let arena = parse(SAMPLE).unwrap();
+        let paths = |terms: &[&str]| {
+            Query::parse(terms)
+                .unwrap()
+                .select(&arena)
+                .iter()
+                .map(|id| path(&arena, *id))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["/a", "/a/e"], paths(&["dirs", "size<100000"]));
+        assert_eq!(vec!["/a/e/i"], paths(&["depth>2"]));
+        assert_eq!(vec!["/", "/a", "/d"], paths(&["dirs", "depth<2"]));
+        assert_eq!(
+            vec!["/d/d.log", "/d/d.ext"],
+            paths(&["files", "path=/d/d.*"])
+        );
+        assert_eq!(
+            vec!["/b.txt", "/c.dat"],
+            paths(&["files", "size>8000000", "depth<2"])
+        );
+        assert_eq!(
+            Err("unknown query term big".to_string()),
+            Query::parse(&["big"])
+        );
+        assert_eq!(
+            Err("bad number in size<lots".to_string()),
+            Query::parse(&["size<lots"])
+        );
+    }
+
+    #[test]
+    fn freeing_space() {
+        let arena = parse(SAMPLE).unwrap();
+        let freed =
+            |disk, required| smallest_freeing(&arena, disk, required).map(|id| path(&arena, id));
+        assert_eq!(Some("/d".to_string()), freed(DISK, UPDATE));
+        assert_eq!(Some("/a/e".to_string()), freed(DISK, 21618836));
+        //already enough room, nothing needs to go
+        assert_eq!(None, freed(100000000, 1));
+        assert_eq!(None, freed(DISK, 21618835));
+        assert_eq!(None, freed(DISK, 60000000));
+    }
+
+    #[test]
+    fn shell_navigation() {
+        let mut sh = Shell::new(parse(SAMPLE).unwrap());
+        assert_eq!(Ok("/\n".to_string()), sh.exec("pwd"));
+        sh.exec("cd a/e").unwrap();
+        assert_eq!(Ok("/a/e\n".to_string()), sh.exec("pwd"));
+        assert_eq!(Ok("584 i\n".to_string()), sh.exec("ls"));
+        sh.exec("cd ../..").unwrap();
+        assert_eq!(
+            Ok("dir e\n29116 f\n2557 g\n62596 h.lst\n".to_string()),
+            sh.exec("ls /a")
+        );
+        assert_eq!(Ok("584\t/a/e\n94853\t/a\n".to_string()), sh.exec("du a"));
+        assert_eq!(
+            Ok("/d/d.log\n/d/d.ext\n".to_string()),
+            sh.exec("find /d path=**.*")
+        );
+        assert_eq!(
+            Err("x: no such file or directory".to_string()),
+            sh.exec("cd x")
+        );
+        assert_eq!(
+            Err("b.txt: not a directory".to_string()),
+            sh.exec("cd b.txt")
+        );
+        assert_eq!(
+            Err("sudo rm: unknown command".to_string()),
+            sh.exec("sudo rm")
+        );
+    }
+
+    #[test]
+    fn shell_changes() {
+        let mut sh = Shell::new(parse(SAMPLE).unwrap());
+        assert_eq!(
+            Ok(
+                "used 48381165 free 21618835 of 70000000, to have 30000000 free remove /d\n"
+                    .to_string()
+            ),
+            sh.exec("df")
+        );
+        sh.exec("cd /a/e").unwrap();
+        sh.exec("rm /a").unwrap();
+        //removing where we are moves us out
+        assert_eq!(Ok("/\n".to_string()), sh.exec("pwd"));
+        assert_eq!(
+            48381165 - 94853,
+            sh.arena[root(&sh.arena).unwrap()].get().size
+        );
+        assert_eq!(Ok("".to_string()), sh.exec("find path=/a**"));
+
+        sh.exec("mkdir new").unwrap();
+        sh.exec("cd new").unwrap();
+        sh.exec("touch big 1000").unwrap();
+        sh.exec("touch empty").unwrap();
+        assert_eq!(Ok("1000 big\n0 empty\n".to_string()), sh.exec("ls"));
+        assert_eq!(Err("big: already exists".to_string()), sh.exec("touch big"));
+        //a total past u32::MAX is refused and leaves the tree as it was
+        sh.exec("mkdir full").unwrap();
+        sh.exec("cd full").unwrap();
+        let before = sh.arena[root(&sh.arena).unwrap()].get().size;
+        assert_eq!(
+            Err("no room for huge".to_string()),
+            sh.exec("touch huge 4294967295")
+        );
+        assert_eq!(Ok("".to_string()), sh.exec("ls"));
+        assert_eq!(before, sh.arena[root(&sh.arena).unwrap()].get().size);
+        sh.exec("cd ..").unwrap();
+        sh.exec("rm full").unwrap();
+        assert_eq!(
+            48381165 - 94853 + 1000,
+            sh.arena[root(&sh.arena).unwrap()].get().size
+        );
+        assert_eq!(Err("can't remove the root".to_string()), sh.exec("rm /"));
+
+        //with /d gone there's room for the update
+        sh.exec("rm /d").unwrap();
+        assert_eq!(
+            Ok(
+                "used 23353670 free 46646330 of 70000000, to have 30000000 free nothing to remove\n"
+                    .to_string()
+            ),
+            sh.exec("df")
+        );
+        assert_eq!(Some(14848514), largest_file(&sh.arena).map(|f| f.size));
+        sh.exec("rm /b.txt").unwrap();
+        assert_eq!(Some(8504156), largest_file(&sh.arena).map(|f| f.size));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let arena = parse(SAMPLE).unwrap();
+        let json = to_json(&arena);
+        assert!(json.contains(r#""name": "e""#));
+        let loaded = from_json(&json).unwrap();
+        assert_eq!(json, to_json(&loaded));
+        assert_eq!(part1(&arena), part1(&loaded));
+        assert_eq!(part2(&arena), part2(&loaded));
+    }
+
+    #[test]
+    fn json_recomputes_dir_sizes() {
+        let json = r#"{"type": "dir", "name": "/", "size": 1, "children": [
+            {"type": "dir", "name": "a", "size": 999, "children": [
+                {"type": "file", "name": "x", "size": 10},
+                {"type": "file", "name": "y", "size": 5}
+            ]},
+            {"type": "file", "name": "z", "size": 1}
+        ]}"#;
+        let arena = from_json(json).unwrap();
+        assert_eq!(16, arena[find(&arena, "/").unwrap()].get().size);
+        assert_eq!(15, arena[find(&arena, "/a").unwrap()].get().size);
+        assert!(from_json(r#"{"type": "disk"}"#).is_err());
+    }
+
+    #[test]
+    fn json_rejects_what_a_transcript_cant_hold() {
+        let load = |children: &str| {
+            from_json(&format!(
+                r#"{{"type": "dir", "name": "/", "size": 0, "children": [{children}]}}"#
+            ))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            Err("the top level must be a dir".to_string()),
+            from_json(r#"{"type": "file", "name": "x", "size": 1}"#)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        );
+        for name in ["", ".", "..", "a/b", "a\\nb", " a", "a "] {
+            let file = format!(r#"{{"type": "file", "name": "{name}", "size": 1}}"#);
+            assert!(load(&file).unwrap_err().starts_with("bad name"), "{name}");
+        }
+        assert_eq!(
+            Err("x appears twice in /a".to_string()),
+            load(
+                r#"{"type": "dir", "name": "a", "size": 0, "children": [
+                    {"type": "file", "name": "x", "size": 1},
+                    {"type": "dir", "name": "x", "size": 0, "children": []}
+                ]}"#
+            )
+        );
+        //sizes that add up past a u32 are refused, not wrapped or panicked on
+        assert_eq!(
+            Err("y makes /a or a parent too large".to_string()),
+            load(
+                r#"{"type": "dir", "name": "a", "size": 0, "children": [
+                    {"type": "file", "name": "x", "size": 4294967295},
+                    {"type": "file", "name": "y", "size": 1}
+                ]}"#
+            )
+        );
+        assert_eq!(
+            Ok(()),
+            load(r#"{"type": "file", "name": "a b", "size": 1}"#)
+        );
+    }
+
+    #[test]
+    fn transcript_round_trip() {
+        let arena = parse(SAMPLE).unwrap();
+        let written = transcript(&arena);
+        assert!(written.starts_with("$ cd /\n$ ls\ndir a\n14848514 b.txt\n"));
+        let reparsed = parse(&written).unwrap();
+        assert_eq!(to_json(&arena), to_json(&reparsed));
+        //writing it again gives exactly the same text
+        assert_eq!(written, transcript(&reparsed));
+    }
+
+    #[test]
+    fn synthetic_transcripts() {
+        //grow a random tree with the shell, then check it survives both formats
+        let mut rng = Lcg::new(7);
+        let mut next = |n: u32| rng.below(n);
+        let mut sh = Shell::new(parse("$ cd /").unwrap());
+        for i in 0..2000 {
+            match next(6) {
+                0 => sh.exec(&format!("mkdir d{i}")).unwrap(),
+                1 => sh.exec("cd ..").unwrap(),
+                2 => {
+                    let dirs = sh.exec("find dirs depth<100").unwrap();
+                    let dirs = dirs.lines().collect::<Vec<_>>();
+                    sh.exec(&format!("cd {}", dirs[next(dirs.len() as u32) as usize]))
+                        .unwrap()
+                }
+                _ => sh
+                    .exec(&format!("touch f{i}.txt {}", next(100000)))
+                    .unwrap(),
+            };
+        }
+        let written = transcript(&sh.arena);
+        let reparsed = parse(&written).unwrap();
+        assert_eq!(to_json(&sh.arena), to_json(&reparsed));
+        assert_eq!(
+            to_json(&sh.arena),
+            to_json(&from_json(&to_json(&reparsed)).unwrap())
+        );
+        assert_eq!(part1(&sh.arena), part1(&reparsed));
+    }
+
+    #[test]
+    fn outlives_input() {
+        let arena = {
+            let input = SAMPLE.to_string();
+            parse(&input).unwrap()
+        };
+        //built once, then used from another thread
+        let paths = std::thread::spawn(move || {
+            arena
+                .iter()
+                .filter_map(|node| arena.get_node_id(node))
+                .map(|id| {
+                    assert_eq!(Some(id), find(&arena, &path(&arena, id)));
+                    path(&arena, id)
+                })
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(14, paths.len());
+        assert!(paths.contains(&"/a/e/i".to_string()));
+    }
 }
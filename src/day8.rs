@@ -4,7 +4,12 @@
 // that is, only look up, down, left, or right from any given tree.
 
 use crate::day8::Direction::{DOWN, LEFT, RIGHT, UP};
-use itertools::chain;
+use crate::image::{pgm, ppm};
+use load_file::load_str;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
 
 #[derive(Debug, Clone)]
 struct Map {
@@ -12,31 +17,108 @@ struct Map {
     col_count: i16,
     trees: Vec<Vec<u32>>,
 }
+#[derive(Debug, PartialEq)]
+enum MapError {
+    Empty,
+    //row and column are counted from 0
+    NotADigit {
+        row: usize,
+        col: usize,
+        ch: char,
+    },
+    Ragged {
+        row: usize,
+        width: usize,
+        expected: usize,
+    },
+    //sides are held as i16
+    TooLarge {
+        rows: usize,
+        cols: usize,
+    },
+}
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Empty => write!(f, "no trees in the map"),
+            MapError::NotADigit { row, col, ch } => {
+                write!(f, "row {row} column {col}: '{ch}' is not a tree height")
+            }
+            MapError::Ragged {
+                row,
+                width,
+                expected,
+            } => write!(f, "row {row} has {width} trees, expected {expected}"),
+            MapError::TooLarge { rows, cols } => write!(
+                f,
+                "{rows} by {cols} is too large, neither side can be over {}",
+                i16::MAX
+            ),
+        }
+    }
+}
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum Direction {
     UP,
     DOWN,
     LEFT,
     RIGHT,
 }
+impl Direction {
+    const ALL: [Direction; 4] = [UP, DOWN, LEFT, RIGHT];
+    fn step(&self) -> (i16, i16) {
+        match self {
+            UP => (-1, 0),
+            DOWN => (1, 0),
+            LEFT => (0, -1),
+            RIGHT => (0, 1),
+        }
+    }
+}
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            UP => "up",
+            DOWN => "down",
+            LEFT => "left",
+            RIGHT => "right",
+        };
+        write!(f, "{name}")
+    }
+}
 impl Map {
-    pub fn new(input: &str) -> Map {
-        let lines = input.split('\n').collect::<Vec<_>>();
+    pub fn new(input: &str) -> Result<Map, MapError> {
         let mut trees: Vec<Vec<u32>> = vec![];
-        lines.iter().for_each(|line| {
+        //lines() so a trailing newline doesn't make an empty row
+        for (row, line) in input.lines().enumerate() {
             let r = line
+                .trim_end()
                 .chars()
-                .into_iter()
-                .collect::<Vec<char>>()
-                .iter()
-                .map(|c| c.to_digit(10).unwrap())
-                .collect::<Vec<_>>();
+                .enumerate()
+                .map(|(col, ch)| ch.to_digit(10).ok_or(MapError::NotADigit { row, col, ch }))
+                .collect::<Result<Vec<_>, _>>()?;
+            if let Some(first) = trees.first() {
+                if first.len() != r.len() {
+                    return Err(MapError::Ragged {
+                        row,
+                        width: r.len(),
+                        expected: first.len(),
+                    });
+                }
+            }
             trees.push(r);
-        });
-        //assume square.
-        Self {
-            row_count: trees.len() as i16,
-            col_count: trees.len() as i16,
-            trees,
+        }
+        if trees.is_empty() || trees[0].is_empty() {
+            return Err(MapError::Empty);
+        }
+        let (rows, cols) = (trees.len(), trees[0].len());
+        match (i16::try_from(rows), i16::try_from(cols)) {
+            (Ok(row_count), Ok(col_count)) => Ok(Self {
+                row_count,
+                col_count,
+                trees,
+            }),
+            _ => Err(MapError::TooLarge { rows, cols }),
         }
     }
 
@@ -48,157 +130,603 @@ impl Map {
             .get(col as usize)
             .unwrap()
     }
-    fn visible_counts(&self, d: Direction, r: i16, c: i16) -> i32 {
-        let mut ri = r;
-        let mut ci = c;
+    // Walks from (r, c) in steps of `step` until the edge or a tree at least as tall.
+    fn look(&self, r: i16, c: i16, step: (i16, i16)) -> Sight {
         let this_height = self.height(r, c);
-        let mut cnt = 0;
-        match d {
-            UP => {
-                ri -= 1;
-                while ri >= 0 {
-                    if self.height(ri, ci) >= this_height {
-                        return cnt;
-                    }
-                    cnt += 1;
-                    ri -= 1;
-                }
-                return cnt;
-            } //- r fix c
-            DOWN => {
-                ri += 1;
-                while ri < self.row_count {
-                    if self.height(ri, ci) >= this_height {
-                        return cnt;
-                    }
-                    cnt += 1;
-                    ri += 1;
-                }
-                return cnt;
-            } //+ r fix c
-            LEFT => {
-                ci -= 1;
-                while ci >= 0 {
-                    if self.height(ri, ci) >= this_height {
-                        return cnt;
-                    }
-                    cnt += 1;
-                    ci -= 1;
-                }
-                return cnt;
-            } //fix r - c
-            RIGHT => {
-                ci += 1;
-                while ci < self.col_count {
-                    let h = self.height(ri, ci);
-                    if self.height(ri, ci) >= this_height {
-                        return cnt;
-                    }
-                    cnt += 1;
-                    ci += 1;
-                }
-                return cnt;
-            } //fix r + c
+        let (dr, dc) = step;
+        //stepping past i16 is off the map as well
+        let next = |ri: i16, ci: i16| Some((ri.checked_add(dr)?, ci.checked_add(dc)?));
+        let on_map = |&(ri, ci): &(i16, i16)| {
+            ri >= 0 && ri < self.row_count && ci >= 0 && ci < self.col_count
         };
-        cnt
+        let mut at = next(r, c);
+        let mut distance = 0;
+        while let Some((ri, ci)) = at.filter(on_map) {
+            //the blocking tree can be seen too
+            distance += 1;
+            if self.height(ri, ci) >= this_height {
+                return Sight {
+                    distance,
+                    blocker: Some((ri as usize, ci as usize, self.height(ri, ci))),
+                };
+            }
+            at = next(ri, ci);
+        }
+        Sight {
+            distance,
+            blocker: None,
+        }
     }
+    #[cfg(test)]
+    fn visible_counts(&self, d: Direction, r: i16, c: i16) -> i32 {
+        self.look(r, c, d.step()).distance as i32
+    }
+    #[cfg(test)]
     fn visible(&self, d: Direction, r: i16, c: i16) -> bool {
-        let mut ri = r;
-        let mut ci = c;
-        let this_height = self.height(r, c);
-        match d {
-            UP => {
-                ri -= 1;
-                while ri >= 0 {
-                    if self.height(ri, ci) >= this_height {
-                        return false;
-                    }
-                    ri -= 1;
-                }
-            } //- r fix c
-            DOWN => {
-                ri += 1;
-                while ri < self.row_count {
-                    if self.height(ri, ci) >= this_height {
-                        return false;
-                    }
-                    ri += 1;
-                }
-            } //+ r fix c
-            LEFT => {
-                ci -= 1;
-                while ci >= 0 {
-                    if self.height(ri, ci) >= this_height {
-                        return false;
-                    }
-                    ci -= 1;
-                }
-            } //fix r - c
-            RIGHT => {
-                ci += 1;
-                while ci < self.col_count {
-                    if self.height(ri, ci) >= this_height {
-                        return false;
-                    }
-                    ci += 1;
-                }
-            } //fix r + c
-        }
-        true
+        self.look(r, c, d.step()).blocker.is_none()
     }
+    #[cfg(test)]
     fn on_edge(&self, r: i16, c: i16) -> bool {
         r == 0 || r == self.row_count - 1 || c == 0 || c == self.col_count - 1
     }
-    fn get_scenic_distances(&self) -> Vec<i32> {
-        let mut total = 0;
-        let mut totals = vec![];
-        for r in 0..self.row_count {
-            for c in 0..self.col_count {
-                let scenic = self.visible_counts(UP, r, c)
-                    * self.visible_counts(DOWN, r, c)
-                    * self.visible_counts(LEFT, r, c)
-                    * self.visible_counts(RIGHT, r, c);
-                totals.push(scenic);
+    // Walks every row and column once from each end, keeping a stack of the
+    // trees that haven't yet been hidden behind a taller one.
+    fn views(&self) -> Views {
+        let rows = self.row_count as usize;
+        let cols = self.col_count as usize;
+        let mut views = Views {
+            cols,
+            visible: vec![false; rows * cols],
+            distances: vec![[0; 4]; rows * cols],
+        };
+        let mut stack = Vec::with_capacity(rows.max(cols));
+        for r in 0..rows {
+            let line = (0..cols).map(|c| (r, c)).collect::<Vec<_>>();
+            self.sweep(line.iter().copied(), LEFT, &mut stack, &mut views);
+            self.sweep(line.iter().rev().copied(), RIGHT, &mut stack, &mut views);
+        }
+        for c in 0..cols {
+            let line = (0..rows).map(|r| (r, c)).collect::<Vec<_>>();
+            self.sweep(line.iter().copied(), UP, &mut stack, &mut views);
+            self.sweep(line.iter().rev().copied(), DOWN, &mut stack, &mut views);
+        }
+        views
+    }
+    // `line` starts at the edge that trees are looking towards.
+    fn sweep(
+        &self,
+        line: impl Iterator<Item = (usize, usize)>,
+        d: Direction,
+        stack: &mut Vec<(usize, u32)>,
+        views: &mut Views,
+    ) {
+        stack.clear();
+        let mut tallest = None;
+        for (k, (r, c)) in line.enumerate() {
+            let h = self.trees[r][c];
+            let cell = r * views.cols + c;
+            if tallest.is_none_or(|t| t < h) {
+                views.visible[cell] = true;
+                tallest = Some(h);
+            }
+            //anything shorter is hidden behind this tree from now on
+            while stack.last().is_some_and(|(_, sh)| *sh < h) {
+                stack.pop();
             }
+            views.distances[cell][d as usize] = stack.last().map_or(k, |(at, _)| k - at) as u16;
+            stack.push((k, h));
         }
+    }
+    #[cfg(test)]
+    fn get_scenic_distances(&self) -> Vec<u64> {
+        let views = self.views();
+        let mut totals = (0..self.row_count as usize)
+            .flat_map(|r| (0..self.col_count as usize).map(move |c| (r, c)))
+            .map(|(r, c)| views.score(r, c))
+            .collect::<Vec<_>>();
         totals.sort();
-        println!("{:?}", totals);
         totals
     }
+    #[cfg(test)]
     fn count_all_visible_trees(&self) -> usize {
-        let mut total = 0;
-        for r in 0..self.row_count {
-            for c in 0..self.col_count {
-                if self.visible(UP, r, c)
-                    || self.visible(DOWN, r, c)
-                    || self.visible(LEFT, r, c)
-                    || self.visible(RIGHT, r, c)
-                {
-                    total += 1;
+        self.views().visible.iter().filter(|v| **v).count()
+    }
+}
+
+// Visibility and viewing distances for every tree, row by row.
+struct Views {
+    cols: usize,
+    visible: Vec<bool>,
+    //indexed by Direction
+    distances: Vec<[u16; 4]>,
+}
+impl Views {
+    fn visible(&self, r: usize, c: usize) -> bool {
+        self.visible[r * self.cols + c]
+    }
+    #[cfg(test)]
+    fn distance(&self, d: Direction, r: usize, c: usize) -> u16 {
+        self.distances[r * self.cols + c][d as usize]
+    }
+    fn score(&self, r: usize, c: usize) -> u64 {
+        self.distances[r * self.cols + c]
+            .iter()
+            .map(|d| *d as u64)
+            .product()
+    }
+}
+
+// Black through red and yellow to white as t goes from 0 to 1.
+fn heat(t: f64) -> [u8; 3] {
+    let channel = |x: f64| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [
+        channel(3.0 * t),
+        channel(3.0 * t - 1.0),
+        channel(3.0 * t - 2.0),
+    ]
+}
+
+impl Map {
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.row_count as usize)
+            .flat_map(move |r| (0..self.col_count as usize).map(move |c| (r, c)))
+    }
+    // Heights 0 to 9 spread over the grey scale, taller is lighter.
+    pub fn height_image(&self) -> Vec<u8> {
+        let pixels = self
+            .cells()
+            .map(|(r, c)| (self.trees[r][c] * 255 / 9) as u8)
+            .collect::<Vec<_>>();
+        pgm(self.col_count as usize, self.row_count as usize, &pixels)
+    }
+    // White where a tree can be seen from outside the grid.
+    pub fn visible_image(&self) -> Vec<u8> {
+        let views = self.views();
+        let pixels = self
+            .cells()
+            .map(|(r, c)| if views.visible(r, c) { 255 } else { 0 })
+            .collect::<Vec<_>>();
+        pgm(self.col_count as usize, self.row_count as usize, &pixels)
+    }
+    // Scenic scores on a log scale so the few very high ones don't wash out the rest.
+    pub fn scenic_image(&self) -> Vec<u8> {
+        let views = self.views();
+        let best = self
+            .cells()
+            .map(|(r, c)| views.score(r, c))
+            .max()
+            .unwrap_or(0);
+        let scale = ((best + 1) as f64).ln().max(f64::MIN_POSITIVE);
+        let pixels = self
+            .cells()
+            .map(|(r, c)| heat(((views.score(r, c) + 1) as f64).ln() / scale))
+            .collect::<Vec<_>>();
+        ppm(self.col_count as usize, self.row_count as usize, &pixels)
+    }
+    pub fn export(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("heights.pgm"), self.height_image())?;
+        fs::write(dir.join("visible.pgm"), self.visible_image())?;
+        fs::write(dir.join("scenic.ppm"), self.scenic_image())
+    }
+}
+
+// A tree's viewing distances, indexed by Direction, and their product.
+#[derive(Debug, PartialEq)]
+struct Scenic {
+    row: usize,
+    col: usize,
+    distances: [u16; 4],
+    score: u64,
+}
+
+// For each direction the first tree at least as tall, which hides this one from that side.
+#[derive(Debug, PartialEq)]
+struct Sightlines {
+    row: usize,
+    col: usize,
+    height: u32,
+    blockers: [Option<(usize, usize, u32)>; 4],
+}
+impl Sightlines {
+    fn visible(&self) -> bool {
+        self.blockers.iter().any(|b| b.is_none())
+    }
+}
+impl fmt::Display for Sightlines {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seen = if self.visible() { "visible" } else { "hidden" };
+        writeln!(
+            f,
+            "tree at row {} column {} height {} is {seen}",
+            self.row, self.col, self.height
+        )?;
+        for d in Direction::ALL {
+            match self.blockers[d as usize] {
+                Some((r, c, h)) => {
+                    writeln!(f, "  {d}: blocked by height {h} at row {r} column {c}")?
+                }
+                None => writeln!(f, "  {d}: clear to the edge")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Map {
+    fn scenic(&self, views: &Views, row: usize, col: usize) -> Scenic {
+        Scenic {
+            row,
+            col,
+            distances: views.distances[row * views.cols + col],
+            score: views.score(row, col),
+        }
+    }
+    // Highest scores first, ties in reading order.
+    pub fn top_scenic(&self, n: usize) -> Vec<Scenic> {
+        let views = self.views();
+        let mut all = self
+            .cells()
+            .map(|(r, c)| self.scenic(&views, r, c))
+            .collect::<Vec<_>>();
+        all.sort_by_key(|s| std::cmp::Reverse(s.score));
+        all.truncate(n);
+        all
+    }
+    pub fn best_scenic(&self) -> Option<Scenic> {
+        self.top_scenic(1).pop()
+    }
+    pub fn explain(&self, row: usize, col: usize) -> Option<Sightlines> {
+        let height = *self.trees.get(row)?.get(col)?;
+        Some(Sightlines {
+            row,
+            col,
+            height,
+            blockers: Direction::ALL.map(|d| self.look(row as i16, col as i16, d.step()).blocker),
+        })
+    }
+    // Visible trees and the best scoring one under any set of sight lines. The
+    // puzzle's four directions use the linear sweeps, anything else walks.
+    pub fn survey(&self, config: &ViewConfig) -> Survey {
+        let fast = (config.steps == Direction::ALL.map(|d| d.step())).then(|| self.views());
+        let mut survey = Survey {
+            visible: 0,
+            best: None,
+        };
+        let mut distances = Vec::with_capacity(config.steps.len());
+        for (r, c) in self.cells() {
+            distances.clear();
+            let visible = match &fast {
+                Some(views) => {
+                    distances.extend(
+                        views.distances[r * views.cols + c]
+                            .iter()
+                            .map(|d| *d as u64),
+                    );
+                    views.visible(r, c)
+                }
+                None => {
+                    let mut visible = false;
+                    for step in &config.steps {
+                        let sight = self.look(r as i16, c as i16, *step);
+                        visible |= sight.blocker.is_none();
+                        distances.push(sight.distance as u64);
+                    }
+                    visible
                 }
+            };
+            if visible {
+                survey.visible += 1;
+            }
+            let score = config.metric.score(&distances);
+            if survey.best.is_none_or(|(_, _, best)| score > best) {
+                survey.best = Some((r, c, score));
             }
         }
+        survey
+    }
+}
 
-        total
+// Rules for choosing treehouse sites. Distance is in steps along rows and columns.
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    sites: usize,
+    min_distance: usize,
+    min_height: u32,
+}
+impl Placement {
+    fn apart(&self, a: &Site, b: &Site) -> bool {
+        a.0.abs_diff(b.0) + a.1.abs_diff(b.1) >= self.min_distance
+    }
+}
+
+//row, column and scenic score
+type Site = (usize, usize, u64);
+
+#[derive(Debug, PartialEq)]
+struct Plan {
+    //best first
+    sites: Vec<Site>,
+    total: u64,
+    //false when the search ran out of budget, so a better plan may exist
+    proven: bool,
+}
+
+//spacing checks the search may make before settling for what it has
+const SEARCH_BUDGET: usize = 20_000_000;
+
+// Branch and bound over the allowed trees, best scores first. A branch is
+// dropped when even the next best scores that are spaced from those already
+// chosen can't beat the best plan so far.
+struct Search<'a> {
+    rules: &'a Placement,
+    candidates: &'a [Site],
+    chosen: Vec<Site>,
+    best: Plan,
+    budget: usize,
+}
+impl Search<'_> {
+    fn spaced(&mut self, c: &Site) -> bool {
+        self.budget = self.budget.saturating_sub(self.chosen.len());
+        self.chosen.iter().all(|b| self.rules.apart(b, c))
+    }
+    fn run(&mut self, from: usize, sum: u64) {
+        if sum > self.best.total {
+            self.best.sites = self.chosen.clone();
+            self.best.total = sum;
+        }
+        let wanted = self.rules.sites - self.chosen.len();
+        if wanted == 0 {
+            return;
+        }
+        for ix in from..self.candidates.len() {
+            if self.budget == 0 {
+                self.best.proven = false;
+                return;
+            }
+            let c = self.candidates[ix];
+            if !self.spaced(&c) {
+                continue;
+            }
+            //scores only go down from here
+            let mut bound = sum + c.2;
+            let mut found = 1;
+            for next in &self.candidates[ix + 1..] {
+                if found == wanted {
+                    break;
+                }
+                if self.spaced(next) {
+                    bound += next.2;
+                    found += 1;
+                }
+            }
+            if bound <= self.best.total {
+                return;
+            }
+            self.chosen.push(c);
+            self.run(ix + 1, sum + c.2);
+            self.chosen.pop();
+        }
+    }
+}
+
+impl Map {
+    // A greedy pick gives the first bound for the search.
+    pub fn place_treehouses(&self, rules: &Placement) -> Plan {
+        let views = self.views();
+        let mut candidates = self
+            .cells()
+            .filter(|(r, c)| self.trees[*r][*c] >= rules.min_height)
+            .map(|(r, c)| (r, c, views.score(r, c)))
+            .filter(|(_, _, score)| *score > 0)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, _, score)| std::cmp::Reverse(*score));
+
+        let mut greedy: Vec<Site> = vec![];
+        for c in &candidates {
+            if greedy.len() < rules.sites && greedy.iter().all(|b| rules.apart(b, c)) {
+                greedy.push(*c);
+            }
+        }
+        let total = greedy.iter().map(|s| s.2).sum();
+        let mut search = Search {
+            rules,
+            candidates: &candidates,
+            chosen: vec![],
+            best: Plan {
+                sites: greedy,
+                total,
+                proven: true,
+            },
+            budget: SEARCH_BUDGET,
+        };
+        search.run(0, 0);
+        search.best
+    }
+}
+
+// What one sight line from a tree reaches.
+#[derive(Debug, PartialEq)]
+struct Sight {
+    distance: usize,
+    blocker: Option<(usize, usize, u32)>,
+}
+
+// How the viewing distances along each sight line combine into one score.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Metric {
+    Product,
+    Sum,
+    Min,
+}
+impl Metric {
+    fn score(&self, distances: &[u64]) -> u64 {
+        match self {
+            Metric::Product => distances.iter().fold(1, |acc, d| acc.saturating_mul(*d)),
+            Metric::Sum => distances.iter().sum(),
+            Metric::Min => distances.iter().copied().min().unwrap_or(0),
+        }
+    }
+}
+
+// The steps a tree looks along, eg (-1, 0) for up, and how to score them.
+#[derive(Debug, Clone, PartialEq)]
+struct ViewConfig {
+    steps: Vec<(i16, i16)>,
+    metric: Metric,
+}
+impl ViewConfig {
+    // Parts 1 and 2 of the puzzle.
+    pub fn puzzle() -> ViewConfig {
+        Self {
+            steps: Direction::ALL.map(|d| d.step()).to_vec(),
+            metric: Metric::Product,
+        }
+    }
+    // The puzzle's directions plus the four diagonals.
+    pub fn compass(metric: Metric) -> ViewConfig {
+        let mut steps = ViewConfig::puzzle().steps;
+        steps.extend([(-1, -1), (-1, 1), (1, -1), (1, 1)]);
+        Self { steps, metric }
+    }
+    pub fn knight(metric: Metric) -> ViewConfig {
+        let steps = vec![
+            (-2, -1),
+            (-2, 1),
+            (-1, -2),
+            (-1, 2),
+            (1, -2),
+            (1, 2),
+            (2, -1),
+            (2, 1),
+        ];
+        Self { steps, metric }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Survey {
+    visible: usize,
+    //row, column and score
+    best: Option<(usize, usize, u64)>,
+}
+
+pub(crate) fn run() {
+    let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+    let survey = m.survey(&ViewConfig::puzzle());
+    println!("Part 1 {}", survey.visible);
+    if let Some((_, _, score)) = survey.best {
+        println!("Part 2 {score}");
+    }
+    if let Some(best) = m.best_scenic() {
+        println!(
+            "best tree row {} column {}, distances up {} down {} left {} right {}",
+            best.row,
+            best.col,
+            best.distances[UP as usize],
+            best.distances[DOWN as usize],
+            best.distances[LEFT as usize],
+            best.distances[RIGHT as usize]
+        );
+    }
+}
+
+pub(crate) fn cli(args: &[String]) {
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["export", dir, ref input @ ..] => {
+            let text = match input.first() {
+                Some(file) => fs::read_to_string(file).unwrap(),
+                None => load_str!("../data/day8.txt").to_string(),
+            };
+            match Map::new(&text) {
+                Ok(m) => {
+                    m.export(Path::new(dir)).unwrap();
+                    println!("wrote heights.pgm, visible.pgm and scenic.ppm to {dir}");
+                }
+                Err(e) => println!("{e}"),
+            }
+        }
+        ["best", n] => {
+            let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+            let n = n.parse().expect("n must be a number");
+            m.top_scenic(n).iter().for_each(|s| {
+                println!(
+                    "{:>10} row {} column {} {:?}",
+                    s.score, s.row, s.col, s.distances
+                )
+            });
+        }
+        ["explain", row, col] => {
+            let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+            let row = row.parse().expect("row must be a number");
+            let col = col.parse().expect("column must be a number");
+            match m.explain(row, col) {
+                Some(sight) => print!("{sight}"),
+                None => println!("row {row} column {col} is outside the map"),
+            }
+        }
+        ["survey", view, metric] => {
+            let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+            let metric = match metric {
+                "product" => Metric::Product,
+                "sum" => Metric::Sum,
+                "min" => Metric::Min,
+                _ => panic!("unknown metric {metric}, expected product, sum or min"),
+            };
+            let config = match view {
+                "puzzle" => ViewConfig {
+                    metric,
+                    ..ViewConfig::puzzle()
+                },
+                "compass" => ViewConfig::compass(metric),
+                "knight" => ViewConfig::knight(metric),
+                _ => panic!("unknown view {view}, expected puzzle, compass or knight"),
+            };
+            let survey = m.survey(&config);
+            println!("{} visible, best {:?}", survey.visible, survey.best);
+        }
+        ["treehouses", sites, distance, ref height @ ..] => {
+            let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+            let rules = Placement {
+                sites: sites.parse().expect("sites must be a number"),
+                min_distance: distance.parse().expect("distance must be a number"),
+                min_height: height
+                    .first()
+                    .map_or(0, |h| h.parse().expect("height must be a number")),
+            };
+            let plan = m.place_treehouses(&rules);
+            plan.sites
+                .iter()
+                .for_each(|(r, c, score)| println!("row {r} column {c} score {score}"));
+            println!("total {}", plan.total);
+            if !plan.proven {
+                println!("search budget ran out, a better plan may exist");
+            }
+        }
+        _ => run(),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::day8::{Direction, Map};
+    use crate::day8::{
+        heat, Direction, Map, MapError, Metric, Placement, Plan, Scenic, Survey, ViewConfig,
+    };
+    use crate::testutil::Lcg;
     use load_file::load_str;
+    use std::time::Instant;
 
     #[test]
     fn check_loads() {
         let s = load_str!("../data/day8.txt");
-        let m = Map::new(s);
+        let m = Map::new(s).unwrap();
         println!("{:?}", m);
     }
 
     #[test]
     fn scenics() {
         let s = load_str!("../data/day8.txt");
-        let m = Map::new(s);
+        let m = Map::new(s).unwrap();
         let t = m.get_scenic_distances();
         println!("{:?}", t);
         //
@@ -206,7 +734,7 @@ mod tests {
     #[test]
     fn all_visible() {
         let s = load_str!("../data/day8.txt");
-        let m = Map::new(s);
+        let m = Map::new(s).unwrap();
         let t = m.count_all_visible_trees();
         println!("{t}");
     }
@@ -214,7 +742,7 @@ mod tests {
     fn visible_counts() {
         //visible_counts
         let s = load_str!("../data/day8.txt");
-        let m = Map::new(s);
+        let m = Map::new(s).unwrap();
         let u = m.visible_counts(Direction::UP, 3, 2);
         assert_eq!(u, 2);
 
@@ -232,7 +760,7 @@ mod tests {
     #[test]
     fn visible() {
         let s = load_str!("../data/day8.txt");
-        let m = Map::new(s);
+        let m = Map::new(s).unwrap();
         assert_eq!(true, m.visible(Direction::UP, 1, 1));
         assert_eq!(true, m.visible(Direction::LEFT, 1, 1));
         assert_eq!(true, m.visible(Direction::UP, 1, 2));
@@ -260,7 +788,7 @@ mod tests {
     #[test]
     fn edge() {
         let s = load_str!("../data/day8.txt");
-        let m = Map::new(s);
+        let m = Map::new(s).unwrap();
         assert_eq!(true, m.on_edge(0, 0));
         assert_eq!(true, m.on_edge(0, 2));
         assert_eq!(true, m.on_edge(0, 3));
@@ -274,4 +802,336 @@ mod tests {
         assert_eq!(false, m.on_edge(3, 2));
         assert_eq!(false, m.on_edge(2, 2));
     }
+    #[test]
+    fn rectangular() {
+        let m = Map::new("3037\n2551\n6533\n").unwrap();
+        assert_eq!(3, m.row_count);
+        assert_eq!(4, m.col_count);
+        assert_eq!(7, m.height(0, 3));
+        assert!(m.on_edge(1, 3));
+        assert!(!m.on_edge(1, 2));
+        assert_eq!(12, m.count_all_visible_trees());
+
+        let m = Map::new("30\n25\n65\n33\n").unwrap();
+        assert_eq!(4, m.row_count);
+        assert_eq!(2, m.col_count);
+        assert_eq!(8, m.count_all_visible_trees());
+    }
+    #[test]
+    fn bad_maps() {
+        assert_eq!(Err(MapError::Empty), Map::new("").map(|_| ()));
+        assert_eq!(Err(MapError::Empty), Map::new("\n").map(|_| ()));
+        assert_eq!(
+            Err(MapError::NotADigit {
+                row: 1,
+                col: 2,
+                ch: 'x'
+            }),
+            Map::new("303\n25x\n").map(|_| ())
+        );
+        assert_eq!(
+            Err(MapError::Ragged {
+                row: 2,
+                width: 2,
+                expected: 3
+            }),
+            Map::new("303\n255\n65\n").map(|_| ())
+        );
+        assert_eq!(
+            "row 1 column 2: 'x' is not a tree height",
+            Map::new("303\n25x").unwrap_err().to_string()
+        );
+        //a side that won't fit an i16 is refused instead of wrapping
+        let wide = "5".repeat(i16::MAX as usize + 1);
+        assert_eq!(
+            Err(MapError::TooLarge {
+                rows: 1,
+                cols: 32768
+            }),
+            Map::new(&wide).map(|_| ())
+        );
+        //the largest allowed can still be looked across, even in long steps
+        let m = Map::new(&wide[1..]).unwrap();
+        assert_eq!(i16::MAX, m.col_count);
+        assert_eq!(i16::MAX as usize, m.survey(&ViewConfig::puzzle()).visible);
+        assert_eq!(
+            i16::MAX as usize,
+            m.survey(&ViewConfig::knight(Metric::Sum)).visible
+        );
+    }
+    //pseudo random forest, heights 0 to 9
+    fn forest(rows: usize, cols: usize, seed: u32) -> String {
+        let mut rng = Lcg::new(seed);
+        (0..rows)
+            .map(|_| {
+                (0..cols)
+                    .map(|_| char::from_digit(rng.below(10), 10).unwrap())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    #[test]
+    fn sample_answers() {
+        let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+        assert_eq!(21, m.count_all_visible_trees());
+        assert_eq!(Some(&8), m.get_scenic_distances().last());
+    }
+    #[test]
+    fn views_match_walking() {
+        for (rows, cols, seed) in [(5, 5, 1), (7, 13, 2), (20, 3, 3), (1, 9, 4), (31, 31, 5)] {
+            let m = Map::new(&forest(rows, cols, seed)).unwrap();
+            let views = m.views();
+            for r in 0..rows {
+                for c in 0..cols {
+                    let (ri, ci) = (r as i16, c as i16);
+                    let walked = [
+                        Direction::UP,
+                        Direction::DOWN,
+                        Direction::LEFT,
+                        Direction::RIGHT,
+                    ];
+                    assert_eq!(
+                        walked.iter().any(|d| m.visible(*d, ri, ci)),
+                        views.visible(r, c)
+                    );
+                    for d in walked {
+                        assert_eq!(m.visible_counts(d, ri, ci), views.distance(d, r, c) as i32);
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    #[ignore = "bench"]
+    fn bench_large_forest() {
+        let m = Map::new(&forest(5000, 5000, 9)).unwrap();
+        let start = Instant::now();
+        let views = m.views();
+        let best = (0..5000)
+            .flat_map(|r| (0..5000).map(move |c| (r, c)))
+            .map(|(r, c)| views.score(r, c))
+            .max();
+        println!("5000x5000: {:?} best {best:?}", start.elapsed());
+    }
+    #[test]
+    fn images() {
+        let m = Map::new("309\n251\n").unwrap();
+        let header = b"P5\n3 2\n255\n";
+        let heights = m.height_image();
+        assert_eq!(header, &heights[..header.len()]);
+        assert_eq!(&[85, 0, 255, 56, 141, 28], &heights[header.len()..]);
+
+        //a two row grid is all edge
+        let visible = m.visible_image();
+        assert_eq!(&[255; 6], &visible[header.len()..]);
+
+        let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+        let visible = m.visible_image();
+        let header = b"P5\n5 5\n255\n";
+        let mask = &visible[header.len()..];
+        assert_eq!(21, mask.iter().filter(|p| **p == 255).count());
+        //the hidden middle tree, row 2 column 2
+        assert_eq!(0, mask[12]);
+
+        let scenic = m.scenic_image();
+        let header = b"P6\n5 5\n255\n";
+        assert_eq!(header, &scenic[..header.len()]);
+        assert_eq!(header.len() + 75, scenic.len());
+        //edge trees score 0 and show black, the best (row 3 column 2) is white
+        assert_eq!(&[0, 0, 0], &scenic[header.len()..header.len() + 3]);
+        let best = header.len() + (3 * 5 + 2) * 3;
+        assert_eq!(&[255, 255, 255], &scenic[best..best + 3]);
+    }
+    #[test]
+    fn heat_ramp() {
+        assert_eq!([0, 0, 0], heat(0.0));
+        assert_eq!([255, 0, 0], heat(1.0 / 3.0));
+        assert_eq!([255, 255, 0], heat(2.0 / 3.0));
+        assert_eq!([255, 255, 255], heat(1.0));
+    }
+    #[test]
+    fn best_scenic() {
+        let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+        assert_eq!(
+            Some(Scenic {
+                row: 3,
+                col: 2,
+                distances: [2, 1, 2, 2],
+                score: 8
+            }),
+            m.best_scenic()
+        );
+        let top = m.top_scenic(3);
+        assert_eq!(
+            vec![8, 6, 4],
+            top.iter().map(|s| s.score).collect::<Vec<_>>()
+        );
+        assert_eq!((2, 1), (top[1].row, top[1].col));
+        assert_eq!(25, m.top_scenic(100).len());
+    }
+    #[test]
+    fn explain() {
+        // 30373
+        // 25512
+        // 65332
+        // 33549
+        // 35390
+        let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+        let hidden = m.explain(1, 3).unwrap();
+        assert!(!hidden.visible());
+        assert_eq!(
+            [
+                Some((0, 3, 7)),
+                Some((2, 3, 3)),
+                Some((1, 2, 5)),
+                Some((1, 4, 2))
+            ],
+            hidden.blockers
+        );
+        let text = hidden.to_string();
+        assert!(text.starts_with("tree at row 1 column 3 height 1 is hidden\n"));
+        assert!(text.contains("  up: blocked by height 7 at row 0 column 3\n"));
+
+        let seen = m.explain(2, 1).unwrap();
+        assert!(seen.visible());
+        assert_eq!(None, seen.blockers[Direction::RIGHT as usize]);
+        assert!(seen.to_string().contains("  right: clear to the edge\n"));
+
+        assert!(m.explain(5, 0).is_none());
+    }
+    #[test]
+    fn puzzle_survey() {
+        let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+        assert_eq!(
+            Survey {
+                visible: 21,
+                best: Some((3, 2, 8))
+            },
+            m.survey(&ViewConfig::puzzle())
+        );
+        //walking gives the same as the sweeps
+        let mut walked = ViewConfig::puzzle();
+        walked.steps.reverse();
+        assert_eq!(m.survey(&ViewConfig::puzzle()), m.survey(&walked));
+        let m = Map::new(&forest(17, 23, 6)).unwrap();
+        assert_eq!(m.survey(&ViewConfig::puzzle()), m.survey(&walked));
+    }
+    #[test]
+    fn other_views() {
+        // 30373
+        // 25512
+        // 65332
+        // 33549
+        // 35390
+        let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+        //the 4 at row 3 column 3 is only seen diagonally, up and to the right
+        let diagonal = ViewConfig::compass(Metric::Product);
+        assert_eq!(None, m.look(3, 3, (-1, 1)).blocker);
+        assert_eq!(1, m.look(3, 3, (-1, 1)).distance);
+        assert_eq!(Some((3, 3, 4)), m.look(2, 2, (1, 1)).blocker);
+        assert_eq!(22, m.survey(&diagonal).visible);
+        //the puzzle's best tree also sees two trees up and to the right
+        assert_eq!(Some((3, 2, 16)), m.survey(&diagonal).best);
+
+        let m = Map::new("11111\n11111\n11911\n11111\n11111").unwrap();
+        let knight = m.survey(&ViewConfig::knight(Metric::Sum));
+        //the 9 sees one knight's move to the edge in all eight directions
+        assert_eq!(Some((2, 2, 8)), knight.best);
+        assert_eq!(1, Metric::Min.score(&[3, 1, 2]));
+        assert_eq!(6, Metric::Sum.score(&[3, 1, 2]));
+        assert_eq!(6, Metric::Product.score(&[3, 1, 2]));
+        assert_eq!(u64::MAX, Metric::Product.score(&[u64::MAX, 2]));
+    }
+    #[test]
+    fn treehouses() {
+        let m = Map::new(load_str!("../data/day8.txt")).unwrap();
+        let rules = Placement {
+            sites: 1,
+            min_distance: 0,
+            min_height: 0,
+        };
+        assert_eq!(
+            Plan {
+                sites: vec![(3, 2, 8)],
+                total: 8,
+                proven: true
+            },
+            m.place_treehouses(&rules)
+        );
+        //the top two, 8 and 6, are far enough apart
+        let rules = Placement { sites: 2, ..rules };
+        assert_eq!(14, m.place_treehouses(&rules).total);
+        //but not once they have to be 3 apart
+        let rules = Placement {
+            min_distance: 3,
+            ..rules
+        };
+        assert_eq!(9, m.place_treehouses(&rules).total);
+        //nothing inside the edge is 6 or taller
+        let rules = Placement {
+            sites: 9,
+            min_distance: 0,
+            min_height: 6,
+        };
+        assert_eq!(
+            Plan {
+                sites: vec![],
+                total: 0,
+                proven: true
+            },
+            m.place_treehouses(&rules)
+        );
+    }
+    #[test]
+    fn treehouses_exhaustive() {
+        for seed in [11, 12, 13] {
+            let m = Map::new(&forest(7, 6, seed)).unwrap();
+            let views = m.views();
+            let cells = m
+                .cells()
+                .map(|(r, c)| (r, c, views.score(r, c)))
+                .filter(|c| c.2 > 0)
+                .collect::<Vec<_>>();
+            let rules = Placement {
+                sites: 3,
+                min_distance: 3,
+                min_height: 0,
+            };
+            let mut best = 0;
+            for i in 0..cells.len() {
+                for j in i + 1..cells.len() {
+                    for k in j + 1..cells.len() {
+                        let (a, b, c) = (&cells[i], &cells[j], &cells[k]);
+                        if rules.apart(a, b) && rules.apart(a, c) && rules.apart(b, c) {
+                            best = best.max(a.2 + b.2 + c.2);
+                        }
+                    }
+                }
+            }
+            let plan = m.place_treehouses(&rules);
+            assert!(plan.proven);
+            assert_eq!(best, plan.total);
+            assert_eq!(plan.total, plan.sites.iter().map(|s| s.2).sum::<u64>());
+        }
+    }
+    #[test]
+    fn treehouses_level_forest() {
+        //every inner tree scores 1, so nothing prunes on score alone
+        let level = vec!["5".repeat(40); 40].join("\n");
+        let m = Map::new(&level).unwrap();
+        let rules = Placement {
+            sites: 20,
+            min_distance: 15,
+            min_height: 0,
+        };
+        let start = std::time::Instant::now();
+        let plan = m.place_treehouses(&rules);
+        assert!(start.elapsed().as_secs() < 30);
+        assert_eq!(plan.sites.len() as u64, plan.total);
+        assert!(plan.total >= 8);
+        for (i, a) in plan.sites.iter().enumerate() {
+            assert!(plan.sites[i + 1..].iter().all(|b| rules.apart(a, b)));
+        }
+    }
 }
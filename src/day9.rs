@@ -1,19 +1,32 @@
-use crate::day9::Cmd::{D, L, R, U};
+use crate::day9::Cmd::{D, DL, DR, L, R, U, UL, UR};
+use crate::image::ppm;
 
 use load_file::load_str;
 use regex::Regex;
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::hash::Hash;
-use std::ops::Sub;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use std::{fs, io, thread};
 
 #[derive(Debug, Clone)]
 
 struct Snake {
     tail: Vec<Point>,
     visited: HashSet<Point>,
+    //how far a knot can get from the one in front before it has to move
+    slack: i64,
+    //one per knot, empty unless tracking was asked for
+    trails: Vec<Trail>,
 }
 impl Snake {
     pub fn new(tail_len: usize) -> Self {
+        Self::with_slack(tail_len, 1)
+    }
+    pub fn with_slack(tail_len: usize, slack: i64) -> Self {
         let mut tail = Vec::new();
         for _ in 0..tail_len + 1 {
             tail.push(Point::new());
@@ -22,177 +35,694 @@ impl Snake {
         Self {
             tail,
             visited: Default::default(),
+            slack,
+            trails: vec![],
         }
     }
-    pub fn accept_cmd(&mut self, c: &Cmd) {
-        match c {
-            U(1) => {
-                self.tail[0].y -= 1;
-            }
-            D(1) => {
-                self.tail[0].y += 1;
-            }
-            L(1) => {
-                self.tail[0].x -= 1;
-            }
-            R(1) => {
-                self.tail[0].x += 1;
+    // Keep a trail for every knot, not just the tail's visited set.
+    pub fn tracking(mut self) -> Self {
+        self.trails = self.tail.iter().map(|p| Trail::new(*p)).collect();
+        self
+    }
+    // Moves the head the full distance one step at a time, letting the
+    // rest of the rope catch up after every step.
+    pub fn accept_cmd(&mut self, c: &Cmd) -> Result<(), RopeError> {
+        self.accept_cmd_with(c, |_| {})
+    }
+    // Same as accept_cmd, calling back with the rope after every step.
+    pub fn accept_cmd_with(
+        &mut self,
+        c: &Cmd,
+        mut on_step: impl FnMut(&Snake),
+    ) -> Result<(), RopeError> {
+        let (dx, dy) = c.step();
+        for _ in 0..c.distance() {
+            let at = self.tail[0];
+            self.tail[0] = match (at.x.checked_add(dx), at.y.checked_add(dy)) {
+                (Some(x), Some(y)) => Point { x, y },
+                _ => return Err(RopeError::Overflow { knot: 0, at }),
+            };
+            if let Some(t) = self.trails.first_mut() {
+                t.record(self.tail[0], true);
             }
-
-            _ => unreachable!(),
+            self.tail_follow()?;
+            on_step(self);
         }
+        Ok(())
     }
-    pub fn tail_follow(&mut self) {
+    pub fn tail_follow(&mut self) -> Result<(), RopeError> {
         for i in 1..self.tail.len() {
-            let (dx, dy) = self.get_dxdy(self.tail[i - 1], self.tail[i]);
+            let (dx, dy) =
+                self.get_dxdy(self.tail[i - 1], self.tail[i])
+                    .ok_or(RopeError::Overflow {
+                        knot: i,
+                        at: self.tail[i],
+                    })?;
 
             self.tail[i].x += dx;
             self.tail[i].y += dy;
+            if let Some(t) = self.trails.get_mut(i) {
+                t.record(self.tail[i], (dx, dy) != (0, 0));
+            }
             if i == self.tail.len() - 1 {
                 self.visited.insert(self.tail[i]);
             }
         }
+        Ok(())
+    }
+    // Once a knot is too far from the one in front it takes one step
+    // towards it, diagonally if they're in different rows and columns.
+    // None if the knots are too far apart to measure.
+    fn get_dxdy(&self, p1: Point, p2: Point) -> Option<(i64, i64)> {
+        let diff = p1.checked_sub(p2)?;
+        let gap = diff.x.checked_abs()?.max(diff.y.checked_abs()?);
+        if gap > self.slack {
+            Some((diff.x.signum(), diff.y.signum()))
+        } else {
+            Some((0, 0))
+        }
+    }
+    // Smallest box (top left, bottom right) holding every tracked knot's path.
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        self.trails
+            .iter()
+            .map(Trail::bounds)
+            .reduce(|(a, b), (c, d)| {
+                (
+                    Point {
+                        x: a.x.min(c.x),
+                        y: a.y.min(c.y),
+                    },
+                    Point {
+                        x: b.x.max(d.x),
+                        y: b.y.max(d.y),
+                    },
+                )
+            })
+    }
+}
+
+impl Snake {
+    // The knot drawn on a cell, the one nearest the head wins.
+    fn knot_at(&self, p: Point) -> Option<usize> {
+        self.tail.iter().position(|k| *k == p)
+    }
+    // One line per row of the box, knots as H,1..9 over the tail's '#' trail.
+    pub fn render(&self, (min, max): (Point, Point)) -> String {
+        let mut out = String::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let p = Point { x, y };
+                out.push(match self.knot_at(p) {
+                    Some(0) => 'H',
+                    Some(i) => char::from_digit(i as u32 % 10, 10).unwrap(),
+                    None if self.visited.contains(&p) => '#',
+                    None => '.',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+    // The same picture as a PPM, each cell a SCALE pixel square.
+    pub fn render_ppm(&self, (min, max): (Point, Point)) -> Vec<u8> {
+        let width = (max.x - min.x + 1) as usize;
+        let height = (max.y - min.y + 1) as usize;
+        let knots = self.tail.len().max(2) - 1;
+        let mut pixels = vec![];
+        for y in min.y..=max.y {
+            let row = (min.x..=max.x)
+                .map(|x| {
+                    let p = Point { x, y };
+                    match self.knot_at(p) {
+                        Some(0) => [255, 0, 0],
+                        //fades from yellow to green down the rope
+                        Some(i) => [(255 * (knots - i) / knots) as u8, 255, 0],
+                        None if self.visited.contains(&p) => [96, 96, 96],
+                        None => [0, 0, 0],
+                    }
+                })
+                .flat_map(|c| std::iter::repeat_n(c, SCALE))
+                .collect::<Vec<_>>();
+            (0..SCALE).for_each(|_| pixels.extend_from_slice(&row));
+        }
+        ppm(width * SCALE, height * SCALE, &pixels)
+    }
+}
+
+const SCALE: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Text,
+    Ppm,
+}
+
+//widest or tallest a frame can be, in cells
+const MAX_FRAME: u64 = 1000;
+
+// The box the head's path fits in, worked out from the commands alone. Every
+// other knot only ever steps towards the one in front, so stays inside it too.
+fn head_bounds(cmds: &[Cmd]) -> Result<(Point, Point), RopeError> {
+    let (mut at, mut min, mut max) = (Point::new(), Point::new(), Point::new());
+    for cmd in cmds {
+        let (dx, dy) = cmd.step();
+        let n = cmd.distance();
+        let x = dx.checked_mul(n).and_then(|d| at.x.checked_add(d));
+        let y = dy.checked_mul(n).and_then(|d| at.y.checked_add(d));
+        at = match (x, y) {
+            (Some(x), Some(y)) => Point { x, y },
+            _ => return Err(RopeError::Overflow { knot: 0, at }),
+        };
+        min = Point {
+            x: min.x.min(at.x),
+            y: min.y.min(at.y),
+        };
+        max = Point {
+            x: max.x.max(at.x),
+            y: max.y.max(at.y),
+        };
+    }
+    Ok((min, max))
+}
+
+// Calls back with every step of the run, framed by the box the whole run fits in.
+// Runs too spread out to draw are refused before any frame is made.
+fn replay(
+    cmds: &[Cmd],
+    tail_size: usize,
+    mut frame: impl FnMut(&Snake, (Point, Point)),
+) -> Result<(), RopeError> {
+    let bounds = head_bounds(cmds)?;
+    let (min, max) = bounds;
+    let (width, height) = (max.x.abs_diff(min.x) + 1, max.y.abs_diff(min.y) + 1);
+    if width > MAX_FRAME || height > MAX_FRAME {
+        return Err(RopeError::FrameTooLarge { width, height });
+    }
+    let mut snake = Snake::new(tail_size);
+    frame(&snake, bounds);
+    for cmd in cmds {
+        snake.accept_cmd_with(cmd, |s| frame(s, bounds))?;
+    }
+    Ok(())
+}
+
+// Plays the run in the terminal, redrawing the screen every delay.
+fn animate(cmds: &[Cmd], tail_size: usize, delay: Duration) -> Result<(), RopeError> {
+    let mut out = io::stdout().lock();
+    replay(cmds, tail_size, |snake, bounds| {
+        write!(out, "\x1b[H\x1b[2J{}", snake.render(bounds)).unwrap();
+        out.flush().unwrap();
+        thread::sleep(delay);
+    })
+}
+
+// Writes frame_00000.txt (or .ppm) onwards into dir, returns how many.
+fn export_frames(cmds: &[Cmd], tail_size: usize, dir: &Path, format: Format) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let mut count = 0;
+    let mut result = Ok(());
+    replay(cmds, tail_size, |snake, bounds| {
+        if result.is_err() {
+            return;
+        }
+        let (ext, bytes) = match format {
+            Format::Text => ("txt", snake.render(bounds).into_bytes()),
+            Format::Ppm => ("ppm", snake.render_ppm(bounds)),
+        };
+        result = fs::write(dir.join(format!("frame_{count:05}.{ext}")), bytes);
+        count += 1;
+    })
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    result.map(|_| count)
+}
+
+#[derive(Debug, Clone)]
+struct Trail {
+    //how many steps ended on each cell, the start counts once
+    visits: HashMap<Point, usize>,
+    //steps the knot actually moved
+    distance: u64,
+}
+impl Trail {
+    pub fn new(start: Point) -> Self {
+        Self {
+            visits: HashMap::from([(start, 1)]),
+            distance: 0,
+        }
     }
-    fn get_dxdy(&self, p1: Point, p2: Point) -> (i16, i16) {
-        let diff = p1 - p2;
-        match (diff.x, diff.y) {
-            (0, 0) => (0, 0),
-            (0, 1) | (1, 0) | (0, -1) | (-1, 0) => (0, 0),
-            (1, 1) | (1, -1) | (-1, 1) | (-1, -1) => (0, 0),
-            (0, 2) => (0, 1),
-            (0, -2) => (0, -1),
-            (2, 0) => (1, 0),
-            (-2, 0) => (-1, 0),
-            (2, 1) => (1, 1),
-            (2, -1) => (1, -1),
-            (-2, 1) => (-1, 1),
-            (-2, -1) => (-1, -1),
-            (1, 2) => (1, 1),
-            (-1, 2) => (-1, 1),
-            (1, -2) => (1, -1),
-            (-1, -2) => (-1, -1),
-            (-2, -2) => (-1, -1),
-            (-2, 2) => (-1, 1),
-            (2, -2) => (1, -1),
-            (2, 2) => (1, 1),
-            _ => panic!("unhandled case: tail - head = {diff:?}"),
+    fn record(&mut self, p: Point, moved: bool) {
+        *self.visits.entry(p).or_default() += 1;
+        if moved {
+            self.distance += 1;
         }
     }
+    pub fn visited(&self) -> usize {
+        self.visits.len()
+    }
+    // Ties go to the top-most, then left-most cell.
+    pub fn most_visited(&self) -> (Point, usize) {
+        self.visits
+            .iter()
+            .map(|(p, n)| (*p, *n))
+            .max_by_key(|(p, n)| (*n, Reverse((p.y, p.x))))
+            .unwrap()
+    }
+    pub fn bounds(&self) -> (Point, Point) {
+        let xs = self.visits.keys().map(|p| p.x);
+        let ys = self.visits.keys().map(|p| p.y);
+        (
+            Point {
+                x: xs.clone().min().unwrap(),
+                y: ys.clone().min().unwrap(),
+            },
+            Point {
+                x: xs.max().unwrap(),
+                y: ys.max().unwrap(),
+            },
+        )
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-struct Point {
-    pub x: i16,
-    pub y: i16,
+pub struct Point {
+    pub x: i64,
+    pub y: i64,
 }
 impl Point {
     pub fn new() -> Self {
         Self { x: 0, y: 0 }
     }
+    // None rather than wrapping when the gap doesn't fit.
+    pub fn checked_sub(self, rhs: Point) -> Option<Point> {
+        Some(Point {
+            x: self.x.checked_sub(rhs.x)?,
+            y: self.y.checked_sub(rhs.y)?,
+        })
+    }
 }
-impl Sub<Point> for Point {
-    type Output = Point;
 
-    fn sub(self, rhs: Point) -> Self::Output {
-        //+1 if -ve -1 if +ve
-        Point {
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
+#[derive(Debug, PartialEq)]
+pub enum RopeError {
+    Overflow { knot: usize, at: Point },
+    //a line that isn't a move
+    BadCommand(String),
+    //a move whose distance doesn't fit an i64
+    TooFar(String),
+    FrameTooLarge { width: u64, height: u64 },
+}
+impl fmt::Display for RopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RopeError::Overflow { knot, at } => {
+                write!(
+                    f,
+                    "knot {knot} would leave the grid from ({},{})",
+                    at.x, at.y
+                )
+            }
+            RopeError::BadCommand(line) => write!(f, "{line:?} is not a move"),
+            RopeError::TooFar(line) => write!(f, "{line}: distance is too large"),
+            RopeError::FrameTooLarge { width, height } => write!(
+                f,
+                "the rope covers {width} by {height} cells, too large to draw over {MAX_FRAME}"
+            ),
         }
     }
 }
 
 #[derive(Debug, PartialOrd, PartialEq)]
 pub enum Cmd {
-    U(i16),
-    D(i16),
-    L(i16),
-    R(i16),
+    U(i64),
+    D(i64),
+    L(i64),
+    R(i64),
+    //diagonal head moves
+    UL(i64),
+    UR(i64),
+    DL(i64),
+    DR(i64),
 }
-impl From<&str> for Cmd {
-    fn from(s: &str) -> Cmd {
+impl Cmd {
+    // Which way a single step goes, y grows downwards.
+    pub fn step(&self) -> (i64, i64) {
+        match self {
+            U(_) => (0, -1),
+            D(_) => (0, 1),
+            L(_) => (-1, 0),
+            R(_) => (1, 0),
+            UL(_) => (-1, -1),
+            UR(_) => (1, -1),
+            DL(_) => (-1, 1),
+            DR(_) => (1, 1),
+        }
+    }
+    pub fn distance(&self) -> i64 {
+        match self {
+            U(n) | D(n) | L(n) | R(n) | UL(n) | UR(n) | DL(n) | DR(n) => *n,
+        }
+    }
+}
+impl TryFrom<&str> for Cmd {
+    type Error = RopeError;
+
+    fn try_from(s: &str) -> Result<Cmd, RopeError> {
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"^([U|D|L|R]) (\d+)$").unwrap();
+            static ref RE: Regex = Regex::new(r"^(UL|UR|DL|DR|U|D|L|R) (\d+)$").unwrap();
         }
-        let caps = RE.captures(s).unwrap();
+        let caps = RE
+            .captures(s)
+            .ok_or_else(|| RopeError::BadCommand(s.to_string()))?;
         let c = caps.get(1).unwrap().as_str();
-        let d = caps.get(2).unwrap().as_str().parse::<i16>().unwrap();
-        match c {
+        //the regex only lets digits through, so the one way to fail is too many
+        let d = caps
+            .get(2)
+            .unwrap()
+            .as_str()
+            .parse::<i64>()
+            .map_err(|_| RopeError::TooFar(s.to_string()))?;
+        Ok(match c {
             "U" => U(d),
             "D" => D(d),
             "L" => L(d),
             "R" => R(d),
+            "UL" => UL(d),
+            "UR" => UR(d),
+            "DL" => DL(d),
+            "DR" => DR(d),
             _ => unreachable!(),
-        }
+        })
     }
 }
-fn parse(l: &str) -> Cmd {
-    Cmd::from(l)
+fn parse(l: &str) -> Result<Cmd, RopeError> {
+    Cmd::try_from(l)
 }
-fn extend(c: &Cmd) -> Vec<Cmd> {
-    let mut res = vec![];
-    match c {
-        U(n) => {
-            for i in 0..*n {
-                res.push(U(1));
-            }
+// One run with every knot tracked, part 1 and 2 both read off it.
+fn simulate(cmds: &[Cmd], tail_size: usize) -> Result<Snake, RopeError> {
+    let mut snake = Snake::new(tail_size).tracking();
+    for cmd in cmds {
+        snake.accept_cmd(cmd)?;
+    }
+    Ok(snake)
+}
+// Commands from a file, or the puzzle input when there's none.
+fn input(file: Option<&&str>) -> Result<Vec<Cmd>, RopeError> {
+    match file {
+        Some(f) => read(&fs::read_to_string(f).unwrap()),
+        None => read(load_str!("../data/day9.txt")),
+    }
+}
+fn read(text: &str) -> Result<Vec<Cmd>, RopeError> {
+    text.trim()
+        .split('\n')
+        .collect::<Vec<_>>()
+        .iter()
+        .map(|l| parse(l))
+        .collect::<Result<Vec<Cmd>, _>>()
+}
+
+pub fn run() {
+    match input(None).and_then(|cmds| simulate(&cmds, 9)) {
+        Ok(snake) => {
+            println!("Part 1 {}", snake.trails[1].visited());
+            println!("Part 2 {}", snake.trails[9].visited());
         }
-        D(n) => {
-            for i in 0..*n {
-                res.push(D(1));
+        Err(e) => println!("{e}"),
+    }
+}
+
+pub(crate) fn cli(args: &[String]) {
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["stats"] => {
+            let snake = match input(None).and_then(|cmds| simulate(&cmds, 9)) {
+                Ok(s) => s,
+                Err(e) => return println!("{e}"),
+            };
+            let (min, max) = snake.bounds().unwrap();
+            println!("bounds ({},{}) to ({},{})", min.x, min.y, max.x, max.y);
+            for (i, t) in snake.trails.iter().enumerate() {
+                let (p, n) = t.most_visited();
+                println!(
+                    "knot {i} visited {:>5} moved {:>6} busiest ({},{}) x{n}",
+                    t.visited(),
+                    t.distance,
+                    p.x,
+                    p.y
+                );
             }
         }
-        L(n) => {
-            for i in 0..*n {
-                res.push(L(1));
+        ["play", ms, ref file @ ..] => {
+            let ms = ms.parse().expect("ms must be a number");
+            let played =
+                input(file.first()).and_then(|cmds| animate(&cmds, 9, Duration::from_millis(ms)));
+            if let Err(e) = played {
+                println!("{e}");
             }
         }
-        R(n) => {
-            for i in 0..*n {
-                res.push(R(1));
+        ["frames", dir, format, ref file @ ..] => {
+            let format = match format {
+                "txt" => Format::Text,
+                "ppm" => Format::Ppm,
+                _ => return println!("format must be txt or ppm"),
+            };
+            let cmds = match input(file.first()) {
+                Ok(cmds) => cmds,
+                Err(e) => return println!("{e}"),
+            };
+            match export_frames(&cmds, 9, Path::new(dir), format) {
+                Ok(n) => println!("wrote {n} frames to {dir}"),
+                Err(e) => println!("{e}"),
             }
         }
-    };
-    res
-}
-fn eval_with_tail_len(cmds: &Vec<Cmd>, tail_size: usize) -> usize {
-    let mut snake = Snake::new(tail_size);
-
-    for cmd in cmds {
-        snake.accept_cmd(cmd);
-        snake.tail_follow();
+        _ => run(),
     }
-
-    snake.visited.len()
-}
-
-pub fn run() {
-    let cmds = load_str!("../data/day9.txt")
-        .split('\n')
-        .collect::<Vec<_>>()
-        .iter()
-        .map(|l| parse(l))
-        .collect::<Vec<Cmd>>()
-        .iter()
-        .flat_map(extend)
-        .collect::<Vec<Cmd>>();
-    println!("Part 1 {}", eval_with_tail_len(&cmds, 1));
-    println!("Part 2 {}", eval_with_tail_len(&cmds, 9));
 }
 #[cfg(test)]
 mod tests {
-    use crate::day9::parse;
     use crate::day9::Cmd::*;
+    use crate::day9::{
+        export_frames, head_bounds, parse, read, replay, simulate, Cmd, Format, Point, RopeError,
+        Snake,
+    };
+
+    const SAMPLE: &str = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2";
+    const LARGER: &str = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20";
+
+    fn cmds(s: &str) -> Vec<Cmd> {
+        s.lines().map(|l| parse(l).unwrap()).collect()
+    }
 
     #[test]
     fn create() {
-        let c = parse("U 3");
+        let c = parse("U 3").unwrap();
         assert_eq!(c, U(3));
-        let c = parse("D 1");
+        let c = parse("D 1").unwrap();
         assert_eq!(c, D(1));
-        let c = parse("L 322");
+        let c = parse("L 322").unwrap();
         assert_eq!(c, L(322));
-        let c = parse("R 4");
+        let c = parse("R 4").unwrap();
         assert_eq!(c, R(4));
+        let c = parse("UL 2").unwrap();
+        assert_eq!(c, UL(2));
+        let c = parse("DR 7").unwrap();
+        assert_eq!(c, DR(7));
+    }
+    #[test]
+    fn samples() {
+        assert_eq!(13, simulate(&cmds(SAMPLE), 1).unwrap().visited.len());
+        assert_eq!(1, simulate(&cmds(SAMPLE), 9).unwrap().visited.len());
+        assert_eq!(36, simulate(&cmds(LARGER), 9).unwrap().visited.len());
+    }
+    #[test]
+    fn follow_rule() {
+        let snake = Snake::new(1);
+        let at = |x, y| Point { x, y };
+        assert_eq!(Some((0, 0)), snake.get_dxdy(at(1, 1), at(0, 0)));
+        assert_eq!(Some((1, 0)), snake.get_dxdy(at(2, 0), at(0, 0)));
+        assert_eq!(Some((-1, 1)), snake.get_dxdy(at(-2, 1), at(0, 0)));
+        assert_eq!(Some((1, 1)), snake.get_dxdy(at(2, 2), at(0, 0)));
+        //used to panic, knots far apart still close in one step at a time
+        assert_eq!(Some((-1, 1)), snake.get_dxdy(at(-7, 3), at(0, 0)));
+
+        let loose = Snake::with_slack(1, 3);
+        assert_eq!(Some((0, 0)), loose.get_dxdy(at(3, -3), at(0, 0)));
+        assert_eq!(Some((1, -1)), loose.get_dxdy(at(4, -1), at(0, 0)));
+    }
+    #[test]
+    fn slack() {
+        //a tail 2 behind only starts moving after the head has gone 3
+        let mut snake = Snake::with_slack(1, 2);
+        snake.accept_cmd(&R(6)).unwrap();
+        assert_eq!(Point { x: 4, y: 0 }, snake.tail[1]);
+        assert_eq!(5, snake.visited.len());
+    }
+    #[test]
+    fn long_moves() {
+        //one command covers the whole distance without expanding it
+        let mut snake = Snake::new(1);
+        snake.accept_cmd(&R(30000)).unwrap();
+        assert_eq!(Point { x: 30000, y: 0 }, snake.tail[0]);
+        assert_eq!(Point { x: 29999, y: 0 }, snake.tail[1]);
+        assert_eq!(30000, snake.visited.len());
+    }
+    #[test]
+    fn diagonal() {
+        let mut snake = Snake::new(2);
+        snake.accept_cmd(&DR(3)).unwrap();
+        assert_eq!(Point { x: 3, y: 3 }, snake.tail[0]);
+        assert_eq!(Point { x: 2, y: 2 }, snake.tail[1]);
+        assert_eq!(Point { x: 1, y: 1 }, snake.tail[2]);
+        snake.accept_cmd(&UL(1)).unwrap();
+        assert_eq!(Point { x: 2, y: 2 }, snake.tail[0]);
+        assert_eq!(Point { x: 2, y: 2 }, snake.tail[1]);
+        assert_eq!(Point { x: 1, y: 1 }, snake.tail[2]);
+        assert_eq!(2, snake.visited.len());
+    }
+    #[test]
+    fn trails() {
+        //tracking agrees with the box worked out from the commands
+        assert_eq!(
+            simulate(&cmds(LARGER), 9).unwrap().bounds(),
+            head_bounds(&cmds(LARGER)).ok()
+        );
+        let snake = simulate(&cmds(SAMPLE), 9).unwrap();
+        assert_eq!(13, snake.trails[1].visited());
+        assert_eq!(1, snake.trails[9].visited());
+        assert_eq!(24, snake.trails[0].distance);
+        assert_eq!(0, snake.trails[9].distance);
+        //the last knot never leaves the start
+        assert_eq!((Point { x: 0, y: 0 }, 25), snake.trails[9].most_visited());
+        let bounds = (Point { x: 0, y: -4 }, Point { x: 5, y: 0 });
+        assert_eq!(bounds, snake.trails[0].bounds());
+        assert_eq!(Some(bounds), snake.bounds());
+
+        let snake = simulate(&cmds(LARGER), 9).unwrap();
+        assert_eq!(36, snake.trails[9].visited());
+        assert_eq!(
+            simulate(&cmds(LARGER), 1).unwrap().visited.len(),
+            snake.trails[1].visited()
+        );
+        //untracked ropes don't pay for it
+        assert!(Snake::new(9).trails.is_empty());
+    }
+    #[test]
+    fn render() {
+        let mut snake = Snake::new(9);
+        let bounds = (Point { x: 0, y: -4 }, Point { x: 5, y: 0 });
+        snake.accept_cmd(&R(4)).unwrap();
+        snake.accept_cmd(&U(4)).unwrap();
+        assert_eq!(
+            "\
+....H.
+....1.
+..432.
+.5....
+6.....
+",
+            snake.render(bounds)
+        );
+        let image = snake.render_ppm(bounds);
+        assert!(image.starts_with(b"P6\n24 20\n255\n"));
+        assert_eq!(24 * 20 * 3, image.len() - b"P6\n24 20\n255\n".len());
+    }
+    #[test]
+    fn frames() {
+        //a frame before the first step and one after each
+        let mut frames = vec![];
+        replay(&read("R 2\nU 1\n").unwrap(), 1, |s, b| {
+            frames.push(s.render(b))
+        })
+        .unwrap();
+        assert_eq!(
+            vec!["...\nH..\n", "...\n1H.\n", "...\n#1H\n", "..H\n#1.\n"],
+            frames
+        );
+
+        //a directory of its own so runs side by side don't collide
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!("day9_frames_{}_{nanos}", std::process::id()));
+        assert_eq!(
+            Ok(4),
+            export_frames(&read("R 2\nU 1").unwrap(), 1, &dir, Format::Text).map_err(|e| e.kind())
+        );
+        assert_eq!(
+            "..H\n#1.\n",
+            std::fs::read_to_string(dir.join("frame_00003.txt")).unwrap()
+        );
+        assert_eq!(
+            Ok(4),
+            export_frames(&read("R 2\nU 1").unwrap(), 1, &dir, Format::Ppm).map_err(|e| e.kind())
+        );
+        assert!(dir.join("frame_00000.ppm").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        //a run too spread out to draw fails before making any frame
+        let mut drawn = 0;
+        assert_eq!(
+            Err(RopeError::FrameTooLarge {
+                width: 1001,
+                height: 6
+            }),
+            replay(&read("R 1000\nU 5").unwrap(), 1, |_, _| drawn += 1)
+        );
+        assert_eq!(0, drawn);
+    }
+    #[test]
+    fn overflow() {
+        //past what i16 could hold
+        let snake = simulate(&read("R 40000\nU 40000").unwrap(), 1).unwrap();
+        assert_eq!(
+            Point {
+                x: 40000,
+                y: -39999
+            },
+            snake.tail[1]
+        );
+        assert_eq!(Ok(L(9_000_000_000)), parse("L 9000000000"));
+        //too far for an i64 is an error, not a panic
+        assert_eq!(
+            Err(RopeError::TooFar("R 99999999999999999999".to_string())),
+            parse("R 99999999999999999999")
+        );
+        assert_eq!(
+            Err(RopeError::BadCommand("X 1".to_string())),
+            read("R 1\nX 1")
+        );
+
+        let edge = Point { x: i64::MAX, y: 0 };
+        assert_eq!(None, edge.checked_sub(Point { x: -1, y: 0 }));
+        assert_eq!(
+            Some(Point { x: i64::MAX, y: 0 }),
+            edge.checked_sub(Point::new())
+        );
+
+        let mut snake = Snake::new(1);
+        snake.tail[0] = Point {
+            x: i64::MAX - 1,
+            y: 0,
+        };
+        snake.tail[1] = Point {
+            x: i64::MAX - 2,
+            y: 0,
+        };
+        snake.accept_cmd(&R(1)).unwrap();
+        assert_eq!(
+            Err(RopeError::Overflow { knot: 0, at: edge }),
+            snake.accept_cmd(&R(1))
+        );
+
+        //too far apart to even measure the gap
+        let mut snake = Snake::with_slack(1, i64::MAX);
+        snake.tail[1] = Point { x: i64::MIN, y: 0 };
+        assert_eq!(
+            Err(RopeError::Overflow {
+                knot: 1,
+                at: Point { x: i64::MIN, y: 0 }
+            }),
+            snake.accept_cmd(&R(1))
+        );
     }
 }
@@ -0,0 +1,14 @@
+// Netpbm writers shared by the days that draw pictures.
+
+// Binary greyscale PGM, one byte a pixel.
+pub(crate) fn pgm(width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+    let mut out = format!("P5\n{width} {height}\n255\n").into_bytes();
+    out.extend_from_slice(pixels);
+    out
+}
+// Binary colour PPM, three bytes a pixel.
+pub(crate) fn ppm(width: usize, height: usize, pixels: &[[u8; 3]]) -> Vec<u8> {
+    let mut out = format!("P6\n{width} {height}\n255\n").into_bytes();
+    pixels.iter().for_each(|p| out.extend_from_slice(p));
+    out
+}
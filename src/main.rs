@@ -17,8 +17,23 @@ mod day6;
 mod day7;
 mod day8;
 mod day9;
+mod image;
+#[cfg(test)]
+mod testutil;
 
 fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some(day) = args.first() {
+        match day.as_str() {
+            "day4" => day4::cli(&args[1..]),
+            "day6" => day6::cli(&args[1..]),
+            "day7" => day7::cli(&args[1..]),
+            "day8" => day8::cli(&args[1..]),
+            "day9" => day9::cli(&args[1..]),
+            _ => println!("no command line options for {day}"),
+        }
+        return;
+    }
     // day1::run();
     // day2::run();
     // day2::run_threaded();
@@ -0,0 +1,17 @@
+// Helpers shared by the days' tests.
+//
+// Benches are #[ignore = "bench"], run them with
+// cargo test --release -- --ignored --nocapture
+
+// Small repeatable pseudo random numbers for generated inputs.
+pub(crate) struct Lcg(u32);
+impl Lcg {
+    pub fn new(seed: u32) -> Lcg {
+        Lcg(seed)
+    }
+    // Next number in 0..n.
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+        (self.0 >> 16) % n
+    }
+}